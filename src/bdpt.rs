@@ -0,0 +1,683 @@
+//! Bidirectional path tracing for the raytracer.
+//!
+//! This module provides a `Bdpt` integrator following Veach's bidirectional
+//! path tracing. For every camera sample it traces one subpath from the
+//! camera and one from a light, then connects every prefix of the camera
+//! subpath to every prefix of the light subpath. Each connection strategy
+//! is weighted with multiple importance sampling (the balance heuristic), so
+//! paths that are easy to find from the light, such as caustics seen
+//! through glass, are no longer left to chance hits from the camera.
+//!
+//! Connections that end on the camera itself (light tracing) can land on any
+//! pixel, and are recorded as splats through `Integrator::ray_color_with_splats`.
+//!
+//! Lights are the objects in the integrator's `lights` list, which should
+//! also be part of the scene and support `Hittable::sample_surface`. Light
+//! escaping to the sky is only found by camera subpaths.
+
+use std::f64::consts::PI;
+
+use crate::{
+    camera::Camera,
+    hittable::{HitRecord, Hittable},
+    hittable_list::HittableList,
+    integrator::{Integrator, Splat, background},
+    interval::Interval,
+    material::Scatter,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::{Color, Point3, Vec3},
+};
+
+/// A bidirectional path tracer.
+///
+/// Strategies are indexed by the number of light subpath vertices `s` and
+/// camera subpath vertices `t`:
+/// - `s = 0`: the camera subpath hits a light by itself
+/// - `s = 1`: a camera vertex is connected to a point sampled on a light
+/// - `t = 1`: a light vertex is connected to the camera and splatted
+/// - otherwise: a camera vertex is connected to a light vertex
+///
+/// Paths are limited to the camera's maximum depth in segments. Surfaces
+/// that only scatter into discrete directions (mirrors and glass) cannot be
+/// connected to and are only crossed by the subpaths themselves.
+pub struct Bdpt {
+    /// The emitting objects that light subpaths start from
+    pub lights: HittableList,
+}
+
+impl Bdpt {
+    /// Creates a new bidirectional path tracer.
+    ///
+    /// # Arguments
+    ///
+    /// * `lights` - The emitting objects that light subpaths start from; they
+    ///   should also be added to the scene
+    pub fn new(lights: HittableList) -> Self {
+        Self { lights }
+    }
+
+    /// Traces a full bidirectional sample for a camera ray.
+    ///
+    /// # Arguments
+    ///
+    /// * `r` - The camera ray
+    /// * `depth` - The maximum number of path segments
+    /// * `world` - The scene to trace through
+    /// * `camera` - The camera, if light tracing strategies are used
+    /// * `splats` - Receives the light tracing contributions
+    /// * `sampler` - The source of random numbers for both subpaths
+    ///
+    /// # Returns
+    ///
+    /// The color contribution of the sample to its own pixel
+    fn sample(
+        &self,
+        r: &Ray,
+        depth: u32,
+        world: &dyn Hittable,
+        camera: Option<&Camera>,
+        splats: &mut Vec<Splat>,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        if depth == 0 {
+            return Color::default();
+        }
+
+        let mut radiance = Color::default();
+        let camera_path = self.camera_subpath(r, depth, world, camera, &mut radiance, sampler);
+        let light_path = self.light_subpath(r, depth, world, sampler);
+
+        for t in 1..=camera_path.len() {
+            for s in 0..=light_path.len() {
+                if s + t < 2 || s + t - 1 > depth as usize || (t == 1 && camera.is_none()) {
+                    continue;
+                }
+
+                radiance += self.connect(
+                    world,
+                    &camera_path,
+                    &light_path,
+                    s,
+                    t,
+                    camera,
+                    splats,
+                    sampler,
+                );
+            }
+        }
+
+        radiance
+    }
+
+    /// Traces the camera subpath.
+    ///
+    /// Light from the sky is added to `radiance` where the subpath escapes,
+    /// since no other strategy can find it.
+    fn camera_subpath(
+        &self,
+        r: &Ray,
+        depth: u32,
+        world: &dyn Hittable,
+        camera: Option<&Camera>,
+        radiance: &mut Color,
+        sampler: &mut dyn Sampler,
+    ) -> Vec<Vertex> {
+        let mut path = Vec::with_capacity(depth as usize + 1);
+        path.push(Vertex {
+            kind: VertexKind::Camera,
+            rec: HitRecord {
+                p: r.origin(),
+                ..Default::default()
+            },
+            r_in: *r,
+            beta: Color::new(1.0, 1.0, 1.0),
+            delta: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        });
+
+        let pdf_dir = camera.map_or(0.0, |c| c.direction_pdf(&r.direction()));
+        let escaped = random_walk(world, *r, path[0].beta, pdf_dir, depth, &mut path, sampler);
+        if let Some((beta, ray)) = escaped {
+            *radiance += beta * background(&ray);
+        }
+
+        path
+    }
+
+    /// Traces the light subpath from a point sampled on a light.
+    fn light_subpath(
+        &self,
+        r: &Ray,
+        depth: u32,
+        world: &dyn Hittable,
+        sampler: &mut dyn Sampler,
+    ) -> Vec<Vertex> {
+        let mut path = Vec::with_capacity(depth as usize);
+        let Some(sample) = self.lights.sample_surface(sampler) else {
+            return path;
+        };
+        if sample.pdf <= 0.0 {
+            return path;
+        }
+
+        // Emit with a cosine distribution around the outward normal.
+        let normal = sample.rec.normal;
+        let mut direction = normal + Vec3::random_unit_vector(sampler);
+        if direction.near_zero() {
+            direction = normal;
+        }
+        let direction = direction.unit_vector();
+        let pdf_dir = normal.dot(&direction) / PI;
+
+        let r_in = Ray::with_wavelength(sample.rec.p, -direction, r.wavelength());
+        let light = Vertex {
+            kind: VertexKind::Light,
+            rec: sample.rec,
+            r_in,
+            beta: Color::new(1.0, 1.0, 1.0) / sample.pdf,
+            delta: false,
+            pdf_fwd: sample.pdf,
+            pdf_rev: 0.0,
+        };
+
+        let ray = Ray::with_wavelength(light.rec.p, direction, r.wavelength());
+        let emitted = light.eval_towards(&(light.rec.p + direction));
+        path.push(light);
+        if pdf_dir <= 0.0 || emitted.near_zero() {
+            return path;
+        }
+
+        let beta = path[0].beta * emitted / pdf_dir;
+        random_walk(world, ray, beta, pdf_dir, depth - 1, &mut path, sampler);
+        path
+    }
+
+    /// Evaluates one connection strategy, weighted by multiple importance sampling.
+    ///
+    /// # Returns
+    ///
+    /// The weighted contribution to the sampled pixel; light tracing
+    /// strategies (`t = 1`) record a splat instead and return black
+    #[allow(clippy::too_many_arguments)]
+    fn connect(
+        &self,
+        world: &dyn Hittable,
+        camera_path: &[Vertex],
+        light_path: &[Vertex],
+        s: usize,
+        t: usize,
+        camera: Option<&Camera>,
+        splats: &mut Vec<Splat>,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        if s == 0 {
+            // The camera subpath hit a light on its own.
+            let pt = &camera_path[t - 1];
+            if pt.kind != VertexKind::Surface {
+                return Color::default();
+            }
+
+            let emitted = pt.emitted();
+            if emitted.near_zero() {
+                return Color::default();
+            }
+
+            let weight = self.mis_weight(camera_path, light_path, None, s, t, camera);
+            return weight * pt.beta * emitted;
+        }
+
+        let qs = &light_path[s - 1];
+        if t == 1 {
+            // Connect the light subpath to the camera and splat it.
+            let Some(camera) = camera else {
+                return Color::default();
+            };
+            let Some(connection) = camera.connect(&qs.rec.p, sampler) else {
+                return Color::default();
+            };
+
+            let sampled = Vertex {
+                kind: VertexKind::Camera,
+                rec: HitRecord {
+                    p: connection.origin,
+                    ..Default::default()
+                },
+                r_in: Ray::default(),
+                beta: Color::new(1.0, 1.0, 1.0),
+                delta: false,
+                pdf_fwd: 0.0,
+                pdf_rev: 0.0,
+            };
+
+            let distance_squared = (qs.rec.p - sampled.rec.p).length_squared();
+            let contribution =
+                qs.beta * qs.eval_towards(&sampled.rec.p) * (connection.pdf / distance_squared);
+            if contribution.near_zero() {
+                return Color::default();
+            }
+
+            let contribution = contribution * visibility(world, &qs.rec.p, &sampled.rec.p, sampler);
+            if contribution.near_zero() {
+                return Color::default();
+            }
+
+            let weight =
+                self.mis_weight(camera_path, light_path, Some(&sampled), s, t, Some(camera));
+            splats.push(Splat {
+                x: connection.x,
+                y: connection.y,
+                color: weight * contribution,
+            });
+            return Color::default();
+        }
+
+        // Connect a camera vertex to a light vertex, possibly on the light itself.
+        let pt = &camera_path[t - 1];
+        let distance_squared = (qs.rec.p - pt.rec.p).length_squared();
+        if distance_squared <= 0.0 {
+            return Color::default();
+        }
+
+        let contribution =
+            qs.beta * qs.eval_towards(&pt.rec.p) * pt.eval_towards(&qs.rec.p) * pt.beta
+                / distance_squared;
+        if contribution.near_zero() {
+            return Color::default();
+        }
+
+        let contribution = contribution * visibility(world, &qs.rec.p, &pt.rec.p, sampler);
+        if contribution.near_zero() {
+            return Color::default();
+        }
+
+        let weight = self.mis_weight(camera_path, light_path, None, s, t, camera);
+        weight * contribution
+    }
+
+    /// Computes the balance heuristic weight of a connection strategy.
+    ///
+    /// The weight is computed from the ratios of the densities with which
+    /// every other strategy would have sampled the same path, following
+    /// Veach's formulation. The densities at the connected vertices are
+    /// recomputed for this connection, and delta vertices, which cannot be
+    /// connected to, are skipped. Without a camera, light tracing strategies
+    /// are not counted.
+    fn mis_weight(
+        &self,
+        camera_path: &[Vertex],
+        light_path: &[Vertex],
+        sampled: Option<&Vertex>,
+        s: usize,
+        t: usize,
+        camera: Option<&Camera>,
+    ) -> f64 {
+        if s + t == 2 {
+            return 1.0;
+        }
+
+        let mut camera_pdfs: Vec<PathDensity> =
+            camera_path[..t].iter().map(PathDensity::of).collect();
+        let mut light_pdfs: Vec<PathDensity> =
+            light_path[..s].iter().map(PathDensity::of).collect();
+
+        let pt = match t == 1 {
+            true => sampled.unwrap(),
+            false => &camera_path[t - 1],
+        };
+        let qs = (s > 0).then(|| &light_path[s - 1]);
+        let pt_minus = (t > 1).then(|| &camera_path[t - 2]);
+        let qs_minus = (s > 1).then(|| &light_path[s - 2]);
+
+        // Update the densities at the ends of the subpaths for this connection.
+        camera_pdfs[t - 1].delta = false;
+        camera_pdfs[t - 1].rev = match qs {
+            Some(qs) => qs.pdf(qs_minus, pt, camera),
+            None => {
+                let pdf = self.lights.surface_pdf(&pt.rec.p);
+                if pdf <= 0.0 {
+                    // No other strategy can sample this light.
+                    return 1.0;
+                }
+                pdf
+            }
+        };
+        if let Some(pt_minus) = pt_minus {
+            camera_pdfs[t - 2].rev = match qs {
+                Some(qs) => pt.pdf(Some(qs), pt_minus, camera),
+                None => pt.emission_pdf(pt_minus),
+            };
+        }
+        if let Some(qs) = qs {
+            light_pdfs[s - 1].delta = false;
+            light_pdfs[s - 1].rev = pt.pdf(pt_minus, qs, camera);
+        }
+        if let (Some(qs), Some(qs_minus)) = (qs, qs_minus) {
+            light_pdfs[s - 2].rev = qs.pdf(Some(pt), qs_minus, camera);
+        }
+
+        let remap = |pdf: f64| match pdf != 0.0 {
+            true => pdf,
+            false => 1.0,
+        };
+
+        let mut sum_ratios = 0.0;
+        let mut ratio = 1.0;
+        for i in (1..t).rev() {
+            ratio *= remap(camera_pdfs[i].rev) / remap(camera_pdfs[i].fwd);
+            let light_tracing = i > 1 || camera.is_some();
+            if light_tracing && !camera_pdfs[i].delta && !camera_pdfs[i - 1].delta {
+                sum_ratios += ratio;
+            }
+        }
+
+        ratio = 1.0;
+        for i in (0..s).rev() {
+            ratio *= remap(light_pdfs[i].rev) / remap(light_pdfs[i].fwd);
+            let previous_delta = i > 0 && light_pdfs[i - 1].delta;
+            if !light_pdfs[i].delta && !previous_delta {
+                sum_ratios += ratio;
+            }
+        }
+
+        1.0 / (1.0 + sum_ratios)
+    }
+}
+
+impl Integrator for Bdpt {
+    /// Computes the color of a ray with every strategy except light tracing.
+    ///
+    /// Without the camera, light subpaths cannot be splatted, so their
+    /// connections to the camera are left out of the estimate and the
+    /// weights of the other strategies.
+    fn ray_color(
+        &self,
+        r: &Ray,
+        depth: u32,
+        world: &dyn Hittable,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        self.sample(r, depth, world, None, &mut Vec::new(), sampler)
+    }
+
+    /// Computes the color of a camera ray with every strategy, splatting
+    /// light subpaths connected to the camera.
+    fn ray_color_with_splats(
+        &self,
+        r: &Ray,
+        depth: u32,
+        world: &dyn Hittable,
+        camera: &Camera,
+        splats: &mut Vec<Splat>,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        self.sample(r, depth, world, Some(camera), splats, sampler)
+    }
+
+    /// Names the integrator, which has no settings beyond its lights.
+    fn description(&self) -> String {
+        "bidirectional path tracer".to_string()
+    }
+}
+
+/// The kind of a subpath vertex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VertexKind {
+    /// A point on the camera lens
+    Camera,
+    /// A point sampled on a light
+    Light,
+    /// A scattering point on a surface
+    Surface,
+    /// A scattering point inside a participating medium, which has no normal
+    Medium,
+}
+
+/// A vertex of a camera or light subpath.
+struct Vertex {
+    /// The kind of vertex
+    kind: VertexKind,
+    /// The position, normal and material of the vertex; only the position
+    /// is meaningful for camera vertices
+    rec: HitRecord,
+    /// The ray that arrived at the vertex
+    r_in: Ray,
+    /// The path throughput up to this vertex, divided by its density
+    beta: Color,
+    /// Whether the subpath left the vertex through a discrete direction
+    delta: bool,
+    /// The area density of sampling this vertex from its subpath
+    pdf_fwd: f64,
+    /// The area density of sampling this vertex from the opposite direction
+    pdf_rev: f64,
+}
+
+impl Vertex {
+    /// Returns true if the vertex lies on a surface, so area densities
+    /// include the cosine at the vertex.
+    fn on_surface(&self) -> bool {
+        matches!(self.kind, VertexKind::Light | VertexKind::Surface)
+    }
+
+    /// Converts a solid angle density at this vertex to an area density at `next`.
+    fn convert_density(&self, pdf: f64, next: &Vertex) -> f64 {
+        let w = next.rec.p - self.rec.p;
+        let distance_squared = w.length_squared();
+        if distance_squared <= 0.0 {
+            return 0.0;
+        }
+
+        let mut pdf = pdf / distance_squared;
+        if next.on_surface() {
+            pdf *= next.rec.normal.dot(&w).abs() / distance_squared.sqrt();
+        }
+        pdf
+    }
+
+    /// Evaluates the scattering (or emission) at the vertex towards a point,
+    /// including the cosine term at the vertex.
+    fn eval_towards(&self, p: &Point3) -> Color {
+        let direction = *p - self.rec.p;
+        match self.kind {
+            VertexKind::Camera => Color::default(),
+            VertexKind::Light => {
+                let cos_theta = self.rec.normal.dot(&direction.unit_vector());
+                if cos_theta <= 0.0 {
+                    return Color::default();
+                }
+                let r_in = Ray::with_wavelength(*p, -direction, self.r_in.wavelength());
+                cos_theta * self.rec.mat.as_ref().unwrap().emitted(&r_in, &self.rec)
+            }
+            VertexKind::Surface | VertexKind::Medium => {
+                let scattered = Ray::with_wavelength(self.rec.p, direction, self.r_in.wavelength());
+                self.rec
+                    .mat
+                    .as_ref()
+                    .unwrap()
+                    .eval(&self.r_in, &self.rec, &scattered)
+            }
+        }
+    }
+
+    /// Returns the radiance emitted by a surface vertex back along its incoming ray.
+    fn emitted(&self) -> Color {
+        self.rec
+            .mat
+            .as_ref()
+            .unwrap()
+            .emitted(&self.r_in, &self.rec)
+    }
+
+    /// Returns the area density of sampling `next` from this vertex.
+    ///
+    /// # Arguments
+    ///
+    /// * `prev` - The vertex the path arrived from; required for surfaces
+    /// * `next` - The vertex being sampled
+    /// * `camera` - The camera, used for the density of camera vertices
+    fn pdf(&self, prev: Option<&Vertex>, next: &Vertex, camera: Option<&Camera>) -> f64 {
+        match self.kind {
+            VertexKind::Camera => {
+                let direction = next.rec.p - self.rec.p;
+                let pdf = camera.map_or(0.0, |c| c.direction_pdf(&direction));
+                self.convert_density(pdf, next)
+            }
+            VertexKind::Light => self.emission_pdf(next),
+            VertexKind::Surface | VertexKind::Medium => {
+                let Some(prev) = prev else {
+                    return 0.0;
+                };
+                let r_in = Ray::with_wavelength(
+                    prev.rec.p,
+                    self.rec.p - prev.rec.p,
+                    self.r_in.wavelength(),
+                );
+                let scattered = Ray::with_wavelength(
+                    self.rec.p,
+                    next.rec.p - self.rec.p,
+                    self.r_in.wavelength(),
+                );
+                let pdf = self
+                    .rec
+                    .mat
+                    .as_ref()
+                    .unwrap()
+                    .scattering_pdf(&r_in, &self.rec, &scattered);
+                self.convert_density(pdf, next)
+            }
+        }
+    }
+
+    /// Returns the area density of a light at this vertex emitting towards `next`.
+    fn emission_pdf(&self, next: &Vertex) -> f64 {
+        let direction = (next.rec.p - self.rec.p).unit_vector();
+        let cos_theta = self.rec.normal.dot(&direction);
+        match cos_theta > 0.0 {
+            true => self.convert_density(cos_theta / PI, next),
+            false => 0.0,
+        }
+    }
+}
+
+/// The densities of a vertex used by the multiple importance sampling weights.
+#[derive(Debug, Clone, Copy)]
+struct PathDensity {
+    /// The area density of sampling the vertex from its subpath
+    fwd: f64,
+    /// The area density of sampling the vertex from the opposite direction
+    rev: f64,
+    /// Whether the vertex scatters into discrete directions
+    delta: bool,
+}
+
+impl PathDensity {
+    /// Returns the densities stored at a vertex.
+    fn of(vertex: &Vertex) -> Self {
+        Self {
+            fwd: vertex.pdf_fwd,
+            rev: vertex.pdf_rev,
+            delta: vertex.delta,
+        }
+    }
+}
+
+/// Extends a subpath by following a ray through the scene.
+///
+/// Vertices are appended to `path` until the path is absorbed, escapes, or
+/// `max_vertices` vertices have been added, updating the forward and
+/// reverse densities of each vertex as it goes.
+///
+/// # Arguments
+///
+/// * `world` - The scene to trace through
+/// * `ray` - The ray leaving the last vertex of the path
+/// * `beta` - The throughput of the path along `ray`
+/// * `pdf_dir` - The solid angle density with which `ray` was sampled
+/// * `max_vertices` - The maximum number of vertices to add
+/// * `path` - The subpath to extend, with at least one vertex
+/// * `sampler` - The source of random numbers
+///
+/// # Returns
+///
+/// The throughput and ray of a path that escaped the scene, or `None`
+fn random_walk(
+    world: &dyn Hittable,
+    mut ray: Ray,
+    mut beta: Color,
+    pdf_dir: f64,
+    max_vertices: u32,
+    path: &mut Vec<Vertex>,
+    sampler: &mut dyn Sampler,
+) -> Option<(Color, Ray)> {
+    let mut pdf_fwd = pdf_dir;
+    for bounces in 0..max_vertices {
+        let Some(rec) = world.hit(&ray, Interval::new(0.001, f64::INFINITY), sampler) else {
+            return Some((beta, ray));
+        };
+
+        let prev = path.last().unwrap();
+        let kind = match rec.in_medium {
+            true => VertexKind::Medium,
+            false => VertexKind::Surface,
+        };
+        let mut vertex = Vertex {
+            kind,
+            rec,
+            r_in: ray,
+            beta,
+            delta: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        };
+        vertex.pdf_fwd = prev.convert_density(pdf_fwd, &vertex);
+
+        if bounces + 1 >= max_vertices {
+            path.push(vertex);
+            break;
+        }
+
+        let mat = vertex.rec.mat.clone().unwrap();
+        let mut scattered = Ray::default();
+        let mut attenuation = Color::default();
+        let mut pdf_rev = 0.0;
+        match mat.scatter(&ray, &vertex.rec, &mut attenuation, &mut scattered, sampler) {
+            Scatter::Absorbed => {
+                path.push(vertex);
+                break;
+            }
+            Scatter::Scattered => {
+                pdf_fwd = mat.scattering_pdf(&ray, &vertex.rec, &scattered);
+                let reverse_in =
+                    Ray::with_wavelength(vertex.rec.p, -scattered.direction(), ray.wavelength());
+                let reverse_out =
+                    Ray::with_wavelength(vertex.rec.p, -ray.direction(), ray.wavelength());
+                pdf_rev = mat.scattering_pdf(&reverse_in, &vertex.rec, &reverse_out);
+            }
+            Scatter::Specular => {
+                pdf_fwd = 0.0;
+                vertex.delta = true;
+            }
+        }
+
+        beta = beta * attenuation;
+        let prev_index = path.len() - 1;
+        path[prev_index].pdf_rev = vertex.convert_density(pdf_rev, &path[prev_index]);
+        path.push(vertex);
+        ray = scattered;
+    }
+
+    None
+}
+
+/// Estimates the fraction of light that travels between two points.
+fn visibility(world: &dyn Hittable, from: &Point3, to: &Point3, sampler: &mut dyn Sampler) -> f64 {
+    let offset = *to - *from;
+    let distance = offset.length();
+    if distance <= 0.002 {
+        return 1.0;
+    }
+
+    let r = Ray::new(*from, offset / distance);
+    world.transmittance(&r, Interval::new(0.001, distance - 0.001), sampler)
+}
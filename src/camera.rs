@@ -4,24 +4,133 @@
 //! for rendering the scene. It supports features like:
 //! - Configurable field of view
 //! - Depth of field
-//! - Anti-aliasing through multiple samples per pixel
-//! - Background color gradient
-//! - Parallel rendering using rayon
+//! - Anti-aliasing through multiple samples per pixel, reconstructed with
+//!   a configurable pixel filter
+//! - Adaptive sampling that stops pixels once their error is small enough
+//! - Progressive rendering in passes, with snapshots and a time budget
+//! - Checkpoints that let interrupted renders resume
+//! - Pluggable sample generation through the `Sampler` trait
+//! - Pluggable light transport through the `Integrator` trait
+//! - Splatting of light paths connected to the camera
+//! - Per-wavelength and spectral (hero wavelength) tracing
+//! - Exposure, tone mapping and color management of the rendered image
+//! - Parallel tile-based rendering using rayon, deterministic for a given seed
 
-use std::{f32::consts::PI, f64, io};
+use std::{
+    f32::consts::PI,
+    f64,
+    fs::{self, File},
+    io::{self, BufWriter, Read, Write},
+    ops::Range,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
 
-use rayon::iter::IntoParallelIterator;
 use rayon::prelude::*;
 
 use crate::{
-    hittable::{HitRecord, Hittable},
-    interval::Interval,
-    random_double,
+    checkpoint::Checkpoint,
+    colorspace::{ColorSpace, transform},
+    debug::heatmap,
+    film::{Film, FilmPixel},
+    filter::{Filter, FilterTable},
+    hittable::Hittable,
+    integrator::{Integrator, PathTracer, Splat},
     ray::Ray,
+    sampler::{IndependentSampler, Sampler},
+    spectrum::{SampledWavelengths, sample_wavelength, wavelength_to_rgb},
+    tile::{Tile, TileOrder, tiles},
+    tonemap::{Exposure, ToneMap},
     vec3::{Color, Point3, Vec3},
-    write_color,
+    write_image,
 };
 
+/// How color is carried along the rays traced by the camera.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMode {
+    /// Each ray carries an RGB color; dispersive materials use a single
+    /// index of refraction
+    #[default]
+    Rgb,
+    /// Each camera sample traces a single random wavelength, which is then
+    /// weighted back into RGB, so dispersive materials split white light
+    Wavelength,
+    /// Each camera sample traces several hero-rotated wavelengths; colors
+    /// are upsampled to spectra and converted back through CIE XYZ
+    Spectral,
+}
+
+impl ColorMode {
+    /// Writes the color mode to a byte stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `out` - The stream to write to
+    ///
+    /// # Returns
+    ///
+    /// An error if writing fails
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let kind: u8 = match self {
+            ColorMode::Rgb => 0,
+            ColorMode::Wavelength => 1,
+            ColorMode::Spectral => 2,
+        };
+        out.write_all(&[kind])
+    }
+
+    /// Reads a color mode written by `write_to` from a byte stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The stream to read from
+    ///
+    /// # Returns
+    ///
+    /// The color mode, or an error if reading fails or the data is invalid
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<Self> {
+        let mut kind = [0];
+        input.read_exact(&mut kind)?;
+        match kind[0] {
+            0 => Ok(ColorMode::Rgb),
+            1 => Ok(ColorMode::Wavelength),
+            2 => Ok(ColorMode::Spectral),
+            kind => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown color mode {}", kind),
+            )),
+        }
+    }
+}
+
+/// What the camera writes out once the render is done.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RenderOutput {
+    /// The rendered image
+    #[default]
+    Image,
+    /// A heatmap of the number of samples each pixel took, from blue for
+    /// none to red for `samples_per_pixel`. Integrators that render the
+    /// whole image themselves write their image instead.
+    SampleHeatmap,
+}
+
+/// A point in the scene connected to the camera by `Camera::connect`.
+#[derive(Debug, Clone, Copy)]
+pub struct CameraConnection {
+    /// The point on the lens the connection ends at
+    pub origin: Point3,
+    /// The horizontal film coordinate the point is seen at, where pixel `i`
+    /// covers \[i,i+1)
+    pub x: f64,
+    /// The vertical film coordinate the point is seen at, where pixel `j`
+    /// covers \[j,j+1)
+    pub y: f64,
+    /// The density of the camera sampling the direction from `origin` to the point
+    pub pdf: f64,
+}
+
 /// A camera that generates rays for rendering the scene.
 ///
 /// The camera is defined by its position, orientation, and various rendering
@@ -32,8 +141,19 @@ pub struct Camera {
     pub aspect_ratio: f64,
     /// Rendered image width in pixel count
     pub image_width: u32,
-    /// Count of random samples for each pixel
+    /// Count of random samples for each pixel, or the most a pixel may take
+    /// with adaptive sampling
     pub samples_per_pixel: u32,
+    /// The reconstruction filter that weights samples and splats around each pixel
+    pub filter: Filter,
+    /// The radius of the reconstruction filter in pixels; a box filter of
+    /// radius 0.5 keeps every sample within its own pixel
+    pub filter_radius: f64,
+    /// The error below which a pixel stops taking samples, as estimated by
+    /// `FilmPixel::error`; 0.0 disables adaptive sampling
+    pub adaptive_threshold: f64,
+    /// The number of samples every pixel takes before adaptive sampling may stop it
+    pub min_samples_per_pixel: u32,
     /// Maximum number of ray bounces into scene
     pub max_depth: u32,
     /// Vertical view angle (field of view)
@@ -48,11 +168,51 @@ pub struct Camera {
     pub defocus_angle: f64,
     /// Distance from camera lookfrom point to plane of perfect focus
     pub focus_dist: f64,
+    /// How color is carried along traced rays
+    pub color_mode: ColorMode,
+    /// The light transport algorithm used to compute the color of camera rays
+    pub integrator: Box<dyn Integrator>,
+    /// The generator of the random numbers used by every camera sample
+    pub sampler: Box<dyn Sampler>,
+    /// The seed every random number of the render is derived from; renders
+    /// with the same seed and settings are identical, whatever the number
+    /// of threads
+    pub seed: u64,
+    /// What is written out once the render is done
+    pub output: RenderOutput,
+    /// The exposure applied to the image before tone mapping
+    pub exposure: Exposure,
+    /// The curve mapping the exposed image into the display range
+    pub tone_map: ToneMap,
+    /// The color space whose linear RGB the scene's colors are given in and
+    /// rendered in; the wavelength and spectral color modes always render
+    /// in sRGB
+    pub working_space: ColorSpace,
+    /// The color space the image is converted to and encoded in when written
+    pub output_space: ColorSpace,
+    /// The number of samples each pixel takes per progressive pass; 0 takes
+    /// all of them in a single pass
+    pub samples_per_pass: u32,
+    /// The time after which no further pass is started
+    pub time_budget: Option<Duration>,
+    /// The file a PPM snapshot of the image is written to after every pass
+    pub snapshot_path: Option<PathBuf>,
+    /// The file the render is checkpointed to between passes; if it exists
+    /// when rendering starts, the render resumes from it
+    pub checkpoint_path: Option<PathBuf>,
+    /// The least time between two checkpoints; the last pass is always checkpointed
+    pub checkpoint_interval: Duration,
+    /// The width and height in pixels of the tiles rendered in parallel
+    pub tile_size: u32,
+    /// The order in which tiles are handed out for rendering
+    pub tile_order: TileOrder,
 
     /// Rendered image height
     image_height: u32,
     /// Color scale factor for a sum of pixel samples
     pixel_samples_scale: f64,
+    /// The reconstruction filter, tabulated for sampling
+    filter_table: FilterTable,
     /// Camera center
     center: Point3,
     /// Location of pixel 0, 0
@@ -80,6 +240,11 @@ impl Default for Camera {
             aspect_ratio: 1.0,
             image_width: 100,
             samples_per_pixel: 10,
+            filter: Filter::default(),
+            filter_radius: 0.5,
+            filter_table: FilterTable::default(),
+            adaptive_threshold: 0.0,
+            min_samples_per_pixel: 16,
             max_depth: 10,
             vfov: 90.0,
             lookfrom: Default::default(),
@@ -96,6 +261,22 @@ impl Default for Camera {
             w: Default::default(),
             defocus_angle: 0.0,
             focus_dist: 10.0,
+            color_mode: ColorMode::default(),
+            integrator: Box::new(PathTracer),
+            sampler: Box::new(IndependentSampler::default()),
+            seed: 0,
+            output: RenderOutput::default(),
+            exposure: Exposure::default(),
+            tone_map: ToneMap::default(),
+            working_space: ColorSpace::default(),
+            output_space: ColorSpace::default(),
+            samples_per_pass: 0,
+            time_budget: None,
+            snapshot_path: None,
+            checkpoint_path: None,
+            checkpoint_interval: Duration::ZERO,
+            tile_size: 16,
+            tile_order: TileOrder::default(),
             defocus_disk_u: Default::default(),
             defocus_disk_v: Default::default(),
         }
@@ -105,6 +286,8 @@ impl Default for Camera {
 impl Camera {
     /// Creates a new camera with the given parameters.
     ///
+    /// Rendering options that are not listed take their default values.
+    ///
     /// # Arguments
     ///
     /// * `aspect_ratio` - Ratio of image width over height
@@ -128,6 +311,7 @@ impl Camera {
     /// * `w` - Camera frame basis vector back
     /// * `defocus_disk_u` - Defocus disk horizontal radius
     /// * `defocus_disk_v` - Defocus disk vertical radius
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         aspect_ratio: f64,
         image_width: u32,
@@ -173,6 +357,7 @@ impl Camera {
             w,
             defocus_disk_u,
             defocus_disk_v,
+            ..Default::default()
         }
     }
 
@@ -181,40 +366,478 @@ impl Camera {
     /// This method performs the actual rendering of the scene, using
     /// parallel processing to generate the image. For each pixel, it:
     /// 1. Generates multiple random samples
-    /// 2. Traces rays through the scene
-    /// 3. Accumulates the color contributions
-    /// 4. Applies gamma correction
+    /// 2. Traces rays through the scene with the camera's integrator
+    /// 3. Accumulates the color contributions, along with any splats the
+    ///    integrator records on other pixels
+    /// 4. Applies exposure, tone mapping and the output color space's
+    ///    primaries and transfer function
     /// 5. Writes the result to stdout
     ///
+    /// With adaptive sampling, a pixel stops taking samples as soon as its
+    /// estimated error falls below `adaptive_threshold`. The samples are
+    /// taken in passes of `samples_per_pass` over the whole image, after
+    /// each of which a snapshot is written to `snapshot_path`, if set. With
+    /// a `checkpoint_path`, the render is checkpointed between passes and
+    /// resumes from the checkpoint if one exists.
+    ///
+    /// Integrators that render the whole image themselves through
+    /// `Integrator::render_image` replace the first three steps.
+    ///
     /// # Arguments
     ///
     /// * `world` - The scene to render
     pub fn render<T: Hittable>(&mut self, world: &T) {
+        let world: &dyn Hittable = world;
+        self.prepare(world);
+
+        let image = match self.integrator.render_image(self, world) {
+            Some(image) => self.develop(&image),
+            None => {
+                let film = self.render_passes(world, |film| self.write_snapshot(film));
+                self.resolve(&film)
+            }
+        };
+
+        write_image(
+            &mut io::stdout(),
+            self.image_width,
+            self.image_height,
+            &image,
+            self.output_space,
+        )
+        .expect("Error writing to output");
+        eprintln!("\rDone.");
+    }
+
+    /// Renders the scene progressively, handing the film to a callback after every pass.
+    ///
+    /// Every pass adds `samples_per_pass` samples to each pixel, so a rough
+    /// image of the whole scene is available early and is refined by the
+    /// following passes. Rendering stops once the pixels have taken
+    /// `samples_per_pixel` samples, or after the first pass to end past
+    /// `time_budget`.
+    ///
+    /// With a `checkpoint_path`, the film is checkpointed between passes,
+    /// and a render started while a checkpoint exists resumes from it. A
+    /// resumed render gives the same image as an uninterrupted one with the
+    /// same settings.
+    ///
+    /// The camera's integrator traces every camera ray; its
+    /// `Integrator::render_image` is not used.
+    ///
+    /// # Arguments
+    ///
+    /// * `world` - The scene to render
+    /// * `on_pass` - Called with the film after every pass
+    ///
+    /// # Returns
+    ///
+    /// The film holding the samples and splats of every pixel
+    pub fn render_progressive<T: Hittable>(
+        &mut self,
+        world: &T,
+        on_pass: impl FnMut(&Film),
+    ) -> Film {
+        let world: &dyn Hittable = world;
+        self.prepare(world);
+        self.render_passes(world, on_pass)
+    }
+
+    /// Prepares the camera and its integrator for rendering a scene.
+    ///
+    /// `render` and `render_progressive` call this themselves; it must be
+    /// called before rendering tiles with `render_tile`.
+    ///
+    /// # Arguments
+    ///
+    /// * `world` - The scene about to be rendered
+    pub fn prepare(&mut self, world: &dyn Hittable) {
         Self::initialize(self);
-        println!("P3\n {0} {1} \n255", self.image_width, self.image_height);
-
-        for j in (0..self.image_height).rev() {
-            eprintln!("\rScanlines remaining: {} ", j);
-            let pixel_colors: Vec<_> = (0..self.image_width)
-                .into_par_iter()
-                .map(|i| {
-                    let mut pixel_color = Color::default();
-                    for _ in 0..self.samples_per_pixel {
-                        let u = (i as f64) + random_double() / (self.image_width - 1) as f64;
-                        let v = (j as f64) + random_double() / (self.image_height - 1) as f64;
-                        let r = self.get_ray(u as u32, v as u32);
-                        pixel_color += Self::ray_color(&r, self.max_depth, world);
-                    }
-                    pixel_color
-                })
-                .collect();
-
-            for pixel_color in pixel_colors {
-                write_color(&mut io::stdout(), &(self.pixel_samples_scale * pixel_color))
-                    .expect("Error writing to output");
+        self.integrator.preprocess(world, self.seed);
+    }
+
+    /// Returns the rendered image height, computed from the width and aspect ratio
+    pub fn image_height(&self) -> u32 {
+        let candidate_image_height = self.image_width as f64 / self.aspect_ratio;
+        match candidate_image_height < 1.0 {
+            true => 1,
+            false => candidate_image_height as u32,
+        }
+    }
+
+    /// Turns a film into the image selected by `output`.
+    ///
+    /// The rendered image is developed with `develop`; sample heatmaps are
+    /// not tone mapped.
+    ///
+    /// # Arguments
+    ///
+    /// * `film` - The film rendered by the camera
+    ///
+    /// # Returns
+    ///
+    /// The display color of every pixel, indexed by `j * image_width + i`
+    pub fn resolve(&self, film: &Film) -> Vec<Color> {
+        match self.output {
+            RenderOutput::Image => self.develop(&film.image()),
+            RenderOutput::SampleHeatmap => film
+                .pixels()
+                .iter()
+                .map(|pixel| heatmap(pixel.samples as f64 * self.pixel_samples_scale))
+                .collect(),
+        }
+    }
+
+    /// Applies the camera's exposure and tone mapping to a linear image and
+    /// converts it to the output color space.
+    ///
+    /// Tone mapping is applied in linear sRGB, whose primaries the ACES and
+    /// AgX matrices and the luminance weights are built for, so the exposed
+    /// image is converted from the space it was rendered in to sRGB and from
+    /// sRGB to the output space around it.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - The linear scene color of every pixel, in the space
+    ///   returned by `rendered_space`
+    ///
+    /// # Returns
+    ///
+    /// The linear display color of every pixel in the output space, ready
+    /// to be encoded with its transfer function
+    pub fn develop(&self, image: &[Color]) -> Vec<Color> {
+        let scale = self.exposure.scale();
+        let to_srgb = self.rendered_space().conversion_to(ColorSpace::Srgb);
+        let to_output = ColorSpace::Srgb.conversion_to(self.output_space);
+        image
+            .iter()
+            .map(|color| {
+                let exposed = transform(&to_srgb, scale * *color);
+                transform(&to_output, self.tone_map.apply(exposed))
+            })
+            .collect()
+    }
+
+    /// Returns the color space the rendered image is in.
+    ///
+    /// RGB renders are in the working space. The wavelength and spectral
+    /// color modes convert their samples to linear sRGB through CIE XYZ,
+    /// whatever the working space.
+    pub fn rendered_space(&self) -> ColorSpace {
+        match self.color_mode {
+            ColorMode::Rgb => self.working_space,
+            ColorMode::Wavelength | ColorMode::Spectral => ColorSpace::Srgb,
+        }
+    }
+
+    /// Samples the image in passes until the sample count or time budget is reached.
+    ///
+    /// # Arguments
+    ///
+    /// * `world` - The scene to render
+    /// * `on_pass` - Called with the film after every pass
+    ///
+    /// # Returns
+    ///
+    /// The film holding the samples and splats of every pixel
+    fn render_passes(&self, world: &dyn Hittable, mut on_pass: impl FnMut(&Film)) -> Film {
+        let start = Instant::now();
+        let pass_samples = match self.samples_per_pass {
+            0 => self.samples_per_pixel,
+            n => n,
+        };
+
+        let (mut film, mut first_sample) = self.resume();
+        let mut last_checkpoint = Instant::now();
+        while first_sample < self.samples_per_pixel {
+            let end_sample = first_sample
+                .saturating_add(pass_samples)
+                .min(self.samples_per_pixel);
+            self.render_pass(world, &mut film, first_sample..end_sample);
+            first_sample = end_sample;
+
+            eprintln!(
+                "\rPass done: {} of {} samples per pixel",
+                first_sample, self.samples_per_pixel
+            );
+            on_pass(&film);
+
+            let out_of_time = self
+                .time_budget
+                .is_some_and(|budget| start.elapsed() >= budget);
+            let last_pass = out_of_time || first_sample >= self.samples_per_pixel;
+            if last_pass || last_checkpoint.elapsed() >= self.checkpoint_interval {
+                film = self.write_checkpoint(film, first_sample);
+                last_checkpoint = Instant::now();
+            }
+            if out_of_time {
+                break;
             }
         }
-        eprintln!("\rDone.");
+
+        film
+    }
+
+    /// Returns the film and sample index a render starts from.
+    ///
+    /// These are taken from the checkpoint at `checkpoint_path` if it
+    /// exists, and are an empty film and the first sample otherwise. A
+    /// checkpoint that cannot be read, or was saved by a render with other
+    /// settings, is moved aside to the same path with `.rejected` appended,
+    /// so the checkpoints of the new render do not overwrite it, and the
+    /// render starts afresh.
+    ///
+    /// # Panics
+    ///
+    /// If a checkpoint has to be moved aside and cannot be
+    fn resume(&self) -> (Film, u32) {
+        let Some(path) = self.checkpoint_path.as_ref().filter(|path| path.exists()) else {
+            return (self.new_film(), 0);
+        };
+
+        let problem = match Checkpoint::load(path) {
+            Ok(checkpoint) => match self.checkpoint_mismatch(&checkpoint) {
+                None => {
+                    eprintln!(
+                        "Resuming from {} at sample {}",
+                        path.display(),
+                        checkpoint.next_sample
+                    );
+                    return (checkpoint.film, checkpoint.next_sample);
+                }
+                Some(setting) => format!("was saved by a render with a different {}", setting),
+            },
+            Err(e) => format!("cannot be read: {}", e),
+        };
+
+        let mut rejected_path = path.clone().into_os_string();
+        rejected_path.push(".rejected");
+        let rejected_path = PathBuf::from(rejected_path);
+        if let Err(e) = fs::rename(path, &rejected_path) {
+            panic!(
+                "Checkpoint {} {}, and it could not be moved to {}: {}",
+                path.display(),
+                problem,
+                rejected_path.display(),
+                e
+            );
+        }
+        eprintln!(
+            "Checkpoint {} {}; moved it to {} and starting a new render",
+            path.display(),
+            problem,
+            rejected_path.display()
+        );
+        (self.new_film(), 0)
+    }
+
+    /// Compares the settings a checkpoint was saved with to the camera's.
+    ///
+    /// # Arguments
+    ///
+    /// * `checkpoint` - The checkpoint to compare
+    ///
+    /// # Returns
+    ///
+    /// The name of the first setting that differs, or `None` if the
+    /// checkpoint was saved by a render with the same settings
+    fn checkpoint_mismatch(&self, checkpoint: &Checkpoint) -> Option<&'static str> {
+        if checkpoint.seed != self.seed {
+            Some("seed")
+        } else if checkpoint.film.width() != self.image_width
+            || checkpoint.film.height() != self.image_height
+        {
+            Some("image size")
+        } else if checkpoint.film.filter() != &self.filter_table {
+            Some("filter")
+        } else if checkpoint.samples_per_pixel != self.samples_per_pixel {
+            Some("sample count")
+        } else if checkpoint.max_depth != self.max_depth {
+            Some("maximum depth")
+        } else if checkpoint.color_mode != self.color_mode {
+            Some("color mode")
+        } else if checkpoint.sampler != self.sampler.description() {
+            Some("sampler")
+        } else if checkpoint.integrator != self.integrator.description() {
+            Some("integrator")
+        } else {
+            None
+        }
+    }
+
+    /// Saves a checkpoint to `checkpoint_path`, if set.
+    ///
+    /// A checkpoint that cannot be saved is reported without stopping the render.
+    ///
+    /// # Arguments
+    ///
+    /// * `film` - The film rendered so far
+    /// * `next_sample` - The index of the first sample of the next pass
+    ///
+    /// # Returns
+    ///
+    /// The film, handed back after saving
+    fn write_checkpoint(&self, film: Film, next_sample: u32) -> Film {
+        let Some(path) = &self.checkpoint_path else {
+            return film;
+        };
+
+        let checkpoint = Checkpoint {
+            seed: self.seed,
+            next_sample,
+            samples_per_pixel: self.samples_per_pixel,
+            max_depth: self.max_depth,
+            color_mode: self.color_mode,
+            sampler: self.sampler.description(),
+            integrator: self.integrator.description(),
+            film,
+        };
+        if let Err(e) = checkpoint.save(path) {
+            eprintln!("Error writing checkpoint to {}: {}", path.display(), e);
+        }
+        checkpoint.film
+    }
+
+    /// Adds a range of samples to every pixel of a film.
+    ///
+    /// The tiles are handed to the threads of the pool in tile order, so the
+    /// tiles the order puts first are rendered first, and every tile is
+    /// rendered by a single thread. Their results are added to the film in
+    /// tile order, so the film does not depend on how the tiles were
+    /// scheduled.
+    ///
+    /// # Arguments
+    ///
+    /// * `world` - The scene to render
+    /// * `film` - The film the samples are added to
+    /// * `sample_indices` - The indices of the samples to take in every pixel
+    fn render_pass(&self, world: &dyn Hittable, film: &mut Film, sample_indices: Range<u32>) {
+        let tiles = tiles(
+            self.image_width,
+            self.image_height,
+            self.tile_size,
+            self.tile_order,
+        );
+        let tiles_done = AtomicUsize::new(0);
+
+        let film_ref = &*film;
+        // The bridge pulls tiles from the iterator one at a time, as threads
+        // become free, unlike splitting the list across the threads.
+        let mut tile_samples: Vec<_> = tiles
+            .iter()
+            .enumerate()
+            .par_bridge()
+            .map(|(index, tile)| {
+                let mut pixels: Vec<FilmPixel> =
+                    tile.pixels().map(|(i, j)| *film_ref.pixel(i, j)).collect();
+                let splats = self.render_tile(world, tile, &mut pixels, sample_indices.clone());
+
+                let done = tiles_done.fetch_add(1, Ordering::Relaxed) + 1;
+                eprint!("\rTiles remaining: {} ", tiles.len() - done);
+                (index, pixels, splats)
+            })
+            .collect();
+        tile_samples.sort_unstable_by_key(|&(index, _, _)| index);
+        eprintln!();
+
+        for (tile, (_, pixels, splats)) in tiles.iter().zip(tile_samples) {
+            for ((i, j), pixel) in tile.pixels().zip(pixels) {
+                *film.pixel_mut(i, j) = pixel;
+            }
+            for splat in splats {
+                film.add_splat(splat.x, splat.y, splat.color);
+            }
+        }
+    }
+
+    /// Adds a range of samples to every pixel of a tile.
+    ///
+    /// The pixels are sampled one after another on the calling thread, so
+    /// the tile's part of the scene stays in that core's caches. The camera
+    /// must have been prepared for the scene with `prepare`.
+    ///
+    /// # Arguments
+    ///
+    /// * `world` - The scene to render
+    /// * `tile` - The tile to render
+    /// * `pixels` - The samples of the tile's pixels so far, in the order of
+    ///   `Tile::pixels`, which the new samples are added to
+    /// * `sample_indices` - The indices of the samples to take in every pixel
+    ///
+    /// # Returns
+    ///
+    /// The splats made by the samples, in pixel order
+    pub fn render_tile(
+        &self,
+        world: &dyn Hittable,
+        tile: &Tile,
+        pixels: &mut [FilmPixel],
+        sample_indices: Range<u32>,
+    ) -> Vec<Splat> {
+        let mut splats = Vec::new();
+        for ((i, j), pixel) in tile.pixels().zip(pixels) {
+            let mut sampler = self.sampler.clone_with_seed(self.seed);
+            for sample_index in sample_indices.clone() {
+                if self.is_converged(pixel) {
+                    break;
+                }
+                sampler.start_pixel_sample(i, j, sample_index);
+                let (r, weight) = self.get_ray(i, j, sampler.as_mut());
+                let color = self.sample_color(&r, world, &mut splats, sampler.as_mut());
+                pixel.add_sample(color, weight);
+            }
+        }
+
+        splats
+    }
+
+    /// Creates an empty film the size of the image, spreading splats with
+    /// the camera's reconstruction filter.
+    ///
+    /// The camera must have been prepared with `prepare`.
+    pub fn new_film(&self) -> Film {
+        Film::with_filter(
+            self.image_width,
+            self.image_height,
+            self.filter_table.clone(),
+        )
+    }
+
+    /// Writes the current image to `snapshot_path`, if set.
+    ///
+    /// A snapshot that cannot be written is reported without stopping the render.
+    ///
+    /// # Arguments
+    ///
+    /// * `film` - The film rendered so far
+    fn write_snapshot(&self, film: &Film) {
+        let Some(path) = &self.snapshot_path else {
+            return;
+        };
+
+        let result = File::create(path).and_then(|file| {
+            write_image(
+                &mut BufWriter::new(file),
+                self.image_width,
+                self.image_height,
+                &self.resolve(film),
+                self.output_space,
+            )
+        });
+        if let Err(e) = result {
+            eprintln!("Error writing snapshot to {}: {}", path.display(), e);
+        }
+    }
+
+    /// Returns whether adaptive sampling stops a pixel from taking more samples.
+    ///
+    /// # Arguments
+    ///
+    /// * `pixel` - The samples the pixel has taken so far
+    fn is_converged(&self, pixel: &FilmPixel) -> bool {
+        self.adaptive_threshold > 0.0
+            && pixel.samples >= self.min_samples_per_pixel.max(2)
+            && pixel.error() < self.adaptive_threshold
     }
 
     /// Initializes the camera's internal state.
@@ -226,14 +849,12 @@ impl Camera {
     /// - Pixel deltas
     /// - Defocus disk parameters
     fn initialize(&mut self) {
-        let candidate_image_height = self.image_width as f64 / self.aspect_ratio;
-        self.image_height = match candidate_image_height < 1.0 {
-            true => 1,
-            false => candidate_image_height as u32,
-        };
+        self.image_height = self.image_height();
 
         self.pixel_samples_scale = 1.0 / self.samples_per_pixel as f64;
 
+        self.filter_table = FilterTable::new(self.filter, self.filter_radius);
+
         self.center = self.lookfrom;
 
         // Determine viewport dimensions.
@@ -272,93 +893,189 @@ impl Camera {
     /// Generates a ray for a given pixel.
     ///
     /// This method constructs a ray from the camera through the specified
-    /// pixel, taking into account depth of field if enabled.
+    /// pixel, taking into account depth of field if enabled. The position
+    /// around the pixel center is sampled from the reconstruction filter
+    /// with the sampler's pixel sample, so the sampler should have just been
+    /// started for this pixel.
     ///
     /// # Arguments
     ///
     /// * `i` - The pixel's x coordinate
     /// * `j` - The pixel's y coordinate
+    /// * `sampler` - The source of random numbers for the pixel and lens positions
     ///
     /// # Returns
     ///
-    /// A ray from the camera through the pixel
-    pub fn get_ray(&self, i: u32, j: u32) -> Ray {
-        // Construct a camera ray originating from the defocus disk and directed at a randomly sampled point around the pixel location i,j.
+    /// A ray from the camera through the pixel, and the filter weight of
+    /// the sample, which is 1.0 wherever the filter is positive and negative
+    /// in its negative lobes
+    pub fn get_ray(&self, i: u32, j: u32, sampler: &mut dyn Sampler) -> (Ray, f64) {
+        // Construct a camera ray originating from the defocus disk and directed at a point sampled from the filter around the pixel location i,j.
 
-        let offset = Self::sample_square();
+        let (u, v) = sampler.get_pixel_2d();
+        let (dx, dy, weight) = self.filter_table.sample(u, v);
         let pixel_sample = self.pixel00_loc
-            + ((i as f64 + offset.x()) * self.pixel_delta_u)
-            + ((j as f64 + offset.y()) * self.pixel_delta_v);
+            + ((i as f64 + dx) * self.pixel_delta_u)
+            + ((j as f64 + dy) * self.pixel_delta_v);
 
         let ray_origin = match self.defocus_angle <= 0.0 {
             true => self.center,
-            false => self.defocus_disk_sample(),
+            false => self.defocus_disk_sample(sampler),
         };
         let ray_direction = pixel_sample - ray_origin;
 
-        Ray::new(ray_origin, ray_direction)
+        (Ray::new(ray_origin, ray_direction), weight)
     }
 
-    /// Computes the color of a ray through the scene.
+    /// Connects a point in the scene to the camera.
     ///
-    /// This method recursively traces a ray through the scene, handling
-    /// reflection, refraction, and background color. It implements the
-    /// Monte Carlo path tracing algorithm.
+    /// A point is sampled on the lens (the camera center for a pinhole
+    /// camera) and the point is projected through it onto the image. This is
+    /// the reverse of `get_ray` and is used by integrators that trace paths
+    /// from the lights.
     ///
     /// # Arguments
     ///
-    /// * `r` - The ray to trace
-    /// * `depth` - The current recursion depth
-    /// * `world` - The scene to trace through
+    /// * `p` - The point in the scene
+    /// * `sampler` - The source of random numbers for the lens position
     ///
     /// # Returns
     ///
-    /// The color contribution of the ray
-    pub fn ray_color<T: Hittable>(r: &Ray, depth: u32, world: &T) -> Color {
-        if depth == 0 {
-            return Color::default();
+    /// The connection, or `None` if the point is behind the camera or
+    /// projects outside the image
+    pub fn connect(&self, p: &Point3, sampler: &mut dyn Sampler) -> Option<CameraConnection> {
+        let origin = match self.defocus_angle <= 0.0 {
+            true => self.center,
+            false => self.defocus_disk_sample(sampler),
+        };
+        let direction = *p - origin;
+        let distance = direction.length();
+        let cos_theta = direction.dot(&-self.w) / distance;
+        if cos_theta <= 0.0 {
+            return None;
         }
-        let rec: Option<HitRecord> = world.hit(r, Interval::new(0.001, f64::INFINITY));
-        match rec {
-            Some(rec) => {
-                let mut scattered = Ray::default();
-                let mut attenuation = Color::default();
-
-                match rec
-                    .mat
-                    .as_ref()
-                    .unwrap()
-                    .scatter(r, &rec, &mut attenuation, &mut scattered)
-                {
-                    true => attenuation * Self::ray_color(&scattered, depth - 1, world),
-                    false => Color::default(),
-                }
-            }
-            None => {
-                let unit_direction = r.direction().unit_vector();
-                let a = (unit_direction.y() + 1.0) * 0.5;
-                (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
-            }
+
+        // The point on the plane of focus that a camera ray aims at to reach p.
+        let focus_point = origin + direction * (self.focus_dist / (cos_theta * distance));
+        let offset = focus_point - self.pixel00_loc;
+        let x = offset.dot(&self.pixel_delta_u) / self.pixel_delta_u.length_squared() + 0.5;
+        let y = offset.dot(&self.pixel_delta_v) / self.pixel_delta_v.length_squared() + 0.5;
+        if x < 0.0 || y < 0.0 || x >= self.image_width as f64 || y >= self.image_height as f64 {
+            return None;
         }
+
+        Some(CameraConnection {
+            origin,
+            x,
+            y,
+            pdf: self.direction_pdf(&direction),
+        })
+    }
+
+    /// Returns the density with which camera rays are sampled in a direction.
+    ///
+    /// Camera rays are spread uniformly over the whole image on the plane
+    /// of focus, so this is the density of one camera sample anywhere in the
+    /// image, with respect to solid angle. It also equals the camera's
+    /// importance times the cosine term, divided by the lens density.
+    ///
+    /// # Arguments
+    ///
+    /// * `direction` - The direction of the ray leaving the lens
+    ///
+    /// # Returns
+    ///
+    /// The solid angle density, or 0.0 for directions behind the camera
+    pub fn direction_pdf(&self, direction: &Vec3) -> f64 {
+        let cos_theta = direction.unit_vector().dot(&-self.w);
+        if cos_theta <= 0.0 {
+            return 0.0;
+        }
+
+        let film_area = self.pixel_delta_u.length()
+            * self.image_width as f64
+            * self.pixel_delta_v.length()
+            * self.image_height as f64;
+        self.focus_dist * self.focus_dist / (film_area * cos_theta.powi(3))
     }
 
-    /// Generates a random offset within a pixel.
+    /// Computes the color contribution of a single camera ray.
+    ///
+    /// In `ColorMode::Wavelength` the ray is assigned a random wavelength
+    /// and the traced radiance is weighted by that wavelength's RGB color.
+    ///
+    /// # Arguments
+    ///
+    /// * `r` - The camera ray
+    /// * `world` - The scene to trace through
+    /// * `splats` - Receives the integrator's contributions to other pixels
+    /// * `sampler` - The source of random numbers for the sample
     ///
     /// # Returns
     ///
-    /// A random 2D offset in the range [-0.5, 0.5]
-    fn sample_square() -> Vec3 {
-        Vec3::new(random_double() - 0.5, random_double() - 0.5, 0.0)
+    /// The color contribution of the sample
+    fn sample_color(
+        &self,
+        r: &Ray,
+        world: &dyn Hittable,
+        splats: &mut Vec<Splat>,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        match self.color_mode {
+            ColorMode::Rgb => self.integrator.ray_color_with_splats(
+                r,
+                self.max_depth,
+                world,
+                self,
+                splats,
+                sampler,
+            ),
+            ColorMode::Wavelength => {
+                let lambda = sample_wavelength(sampler.get_1d());
+                let weight = wavelength_to_rgb(lambda);
+                let r = Ray::with_wavelength(r.origin(), r.direction(), Some(lambda));
+
+                let first_splat = splats.len();
+                let color = self.integrator.ray_color_with_splats(
+                    &r,
+                    self.max_depth,
+                    world,
+                    self,
+                    splats,
+                    sampler,
+                );
+                for splat in &mut splats[first_splat..] {
+                    splat.color = splat.color * weight;
+                }
+                color * weight
+            }
+            ColorMode::Spectral => {
+                let mut lambda = SampledWavelengths::sample_uniform(sampler.get_1d());
+                let r = Ray::with_wavelength(r.origin(), r.direction(), Some(lambda.hero()));
+                let radiance = self.integrator.ray_color_spectral(
+                    &r,
+                    &mut lambda,
+                    self.max_depth,
+                    world,
+                    sampler,
+                );
+                lambda.to_rgb(&radiance)
+            }
+        }
     }
 
     /// Generates a random point in the camera's defocus disk.
     ///
+    /// # Arguments
+    ///
+    /// * `sampler` - The source of random numbers
+    ///
     /// # Returns
     ///
     /// A random point within the defocus disk
-    fn defocus_disk_sample(&self) -> Point3 {
+    fn defocus_disk_sample(&self, sampler: &mut dyn Sampler) -> Point3 {
         // Returns a random point in the camera defocus disk
-        let p = Vec3::random_in_unit_disk();
+        let p = Vec3::random_in_unit_disk(sampler);
         self.center + (p.x() * self.defocus_disk_u) + (p.y() * self.defocus_disk_v)
     }
 }
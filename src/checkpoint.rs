@@ -0,0 +1,215 @@
+//! Checkpoints of renders in progress.
+//!
+//! This module provides `Checkpoint`, which saves the state of a progressive
+//! render between passes so that it can be resumed after the process is
+//! stopped. The state is small: the camera's samplers derive every random
+//! number from the seed, the pixel and the sample index, so besides the
+//! film only the seed and the index of the next sample to take are needed.
+//! A resumed render therefore produces exactly the same image as one that
+//! was never interrupted. The other settings that change the image, such
+//! as the sample count, the sampler and the integrator, are recorded too,
+//! so that a checkpoint is only resumed by the render it was saved by.
+//!
+//! The checkpoints of renders of the same scene with different seeds, for
+//! example on several machines, can be merged with `merge_files`.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    camera::ColorMode,
+    film::{Film, read_u32, read_u64},
+};
+
+/// The bytes every checkpoint file starts with.
+const MAGIC: &[u8; 8] = b"RTCKPT03";
+
+/// The longest description accepted, which guards against corrupt lengths.
+const MAX_DESCRIPTION_LEN: u32 = 1 << 16;
+
+/// The state of a progressive render after a pass.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    /// The seed of the render
+    pub seed: u64,
+    /// The index of the first sample of the next pass in every pixel
+    pub next_sample: u32,
+    /// The number of samples every pixel takes by the end of the render
+    pub samples_per_pixel: u32,
+    /// The maximum number of ray bounces
+    pub max_depth: u32,
+    /// How color is carried along the rays
+    pub color_mode: ColorMode,
+    /// The description of the sampler, from `Sampler::description`
+    pub sampler: String,
+    /// The description of the integrator, from `Integrator::description`
+    pub integrator: String,
+    /// The samples and splats recorded so far
+    pub film: Film,
+}
+
+impl Checkpoint {
+    /// Saves the checkpoint to a file.
+    ///
+    /// The checkpoint is first written next to the file and then moved over
+    /// it, so a process stopped while saving leaves the previous checkpoint
+    /// intact.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file to save to
+    ///
+    /// # Returns
+    ///
+    /// An error if writing fails
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let partial_path = path.with_extension("partial");
+        let mut out = BufWriter::new(File::create(&partial_path)?);
+        self.write_to(&mut out)?;
+        out.into_inner()?.sync_all()?;
+        fs::rename(partial_path, path)
+    }
+
+    /// Loads a checkpoint saved by `save`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file to load from
+    ///
+    /// # Returns
+    ///
+    /// The checkpoint, or an error if reading fails or the file is not a checkpoint
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::read_from(&mut BufReader::new(File::open(path)?))
+    }
+
+    /// Writes the checkpoint to a byte stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `out` - The stream to write to
+    ///
+    /// # Returns
+    ///
+    /// An error if writing fails
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&self.seed.to_le_bytes())?;
+        out.write_all(&self.next_sample.to_le_bytes())?;
+        out.write_all(&self.samples_per_pixel.to_le_bytes())?;
+        out.write_all(&self.max_depth.to_le_bytes())?;
+        self.color_mode.write_to(out)?;
+        write_string(out, &self.sampler)?;
+        write_string(out, &self.integrator)?;
+        self.film.write_to(out)
+    }
+
+    /// Reads a checkpoint written by `write_to` from a byte stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The stream to read from
+    ///
+    /// # Returns
+    ///
+    /// The checkpoint, or an error if reading fails or the stream is not a checkpoint
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<Self> {
+        let mut magic = [0; MAGIC.len()];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a render checkpoint",
+            ));
+        }
+
+        Ok(Self {
+            seed: read_u64(input)?,
+            next_sample: read_u32(input)?,
+            samples_per_pixel: read_u32(input)?,
+            max_depth: read_u32(input)?,
+            color_mode: ColorMode::read_from(input)?,
+            sampler: read_string(input)?,
+            integrator: read_string(input)?,
+            film: Film::read_from(input)?,
+        })
+    }
+}
+
+/// Writes a string as its length followed by its UTF-8 bytes.
+fn write_string<W: Write>(out: &mut W, s: &str) -> io::Result<()> {
+    out.write_all(&(s.len() as u32).to_le_bytes())?;
+    out.write_all(s.as_bytes())
+}
+
+/// Reads a string written by `write_string`.
+fn read_string<R: Read>(input: &mut R) -> io::Result<String> {
+    let len = read_u32(input)?;
+    if len > MAX_DESCRIPTION_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("description of {} bytes is too long", len),
+        ));
+    }
+
+    let mut bytes = vec![0; len as usize];
+    input.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Merges the films of several checkpoints into one.
+///
+/// The checkpoints should come from renders of the same scene and camera
+/// with different seeds; renders sharing a seed took the same samples, so
+/// merging them adds no information and a warning is printed.
+///
+/// # Arguments
+///
+/// * `paths` - The checkpoint files to merge
+///
+/// # Returns
+///
+/// The merged film, or an error if a file cannot be read, no files are
+/// given or the films differ in size
+pub fn merge_files(paths: &[PathBuf]) -> io::Result<Film> {
+    let mut merged: Option<Film> = None;
+    let mut seeds = Vec::with_capacity(paths.len());
+    for path in paths {
+        let checkpoint = Checkpoint::load(path)?;
+        if seeds.contains(&checkpoint.seed) {
+            eprintln!(
+                "Warning: {} has the same seed ({}) as an earlier checkpoint",
+                path.display(),
+                checkpoint.seed
+            );
+        }
+        seeds.push(checkpoint.seed);
+
+        match &mut merged {
+            None => merged = Some(checkpoint.film),
+            Some(film) => {
+                if film.width() != checkpoint.film.width()
+                    || film.height() != checkpoint.film.height()
+                {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "{} is {}x{} but earlier checkpoints are {}x{}",
+                            path.display(),
+                            checkpoint.film.width(),
+                            checkpoint.film.height(),
+                            film.width(),
+                            film.height()
+                        ),
+                    ));
+                }
+                film.merge(&checkpoint.film);
+            }
+        }
+    }
+
+    merged.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no checkpoints to merge"))
+}
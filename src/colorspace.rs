@@ -0,0 +1,212 @@
+//! RGB color spaces for the raytracer.
+//!
+//! Colors are rendered as linear RGB in a working color space, and the
+//! image is converted to an output color space and encoded with its
+//! transfer function when it is written. This module provides:
+//! - `ColorSpace`: sRGB, Rec.709, Rec.2020 and Display P3, each with its
+//!   primaries, D65 white point and transfer function
+//! - Conversion of linear colors between color spaces through CIE XYZ
+//! - Decoding of encoded input colors, such as texels or colors picked in
+//!   other tools, to linear working-space colors
+//!
+//! sRGB and Rec.709 share their primaries but not their transfer function,
+//! while Display P3 has wider primaries but the sRGB transfer function.
+
+use crate::vec3::Color;
+
+/// A 3x3 matrix, given as rows, that transforms linear colors.
+pub type ColorMatrix = [[f64; 3]; 3];
+
+/// The chromaticity of the D65 white point shared by every color space.
+const D65: (f64, f64) = (0.3127, 0.3290);
+
+/// The constant α of the Rec.709 and Rec.2020 transfer function.
+const REC_ALPHA: f64 = 1.099_296_826_809_44;
+/// The linear value where the Rec.709 and Rec.2020 transfer function
+/// switches from its linear segment to its power curve.
+const REC_BETA: f64 = 0.018_053_968_510_807;
+
+/// An RGB color space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorSpace {
+    /// sRGB (IEC 61966-2-1), the usual color space of images and monitors
+    #[default]
+    Srgb,
+    /// ITU-R BT.709: the sRGB primaries with the HD video transfer function
+    Rec709,
+    /// ITU-R BT.2020: the wide gamut of UHD video with the Rec.709 transfer function
+    Rec2020,
+    /// Display P3: the DCI-P3 primaries with a D65 white point and the sRGB
+    /// transfer function, as used by recent Apple displays
+    DisplayP3,
+}
+
+impl ColorSpace {
+    /// Returns the chromaticities of the red, green and blue primaries.
+    fn primaries(&self) -> [(f64, f64); 3] {
+        match self {
+            ColorSpace::Srgb | ColorSpace::Rec709 => [(0.64, 0.33), (0.30, 0.60), (0.15, 0.06)],
+            ColorSpace::Rec2020 => [(0.708, 0.292), (0.170, 0.797), (0.131, 0.046)],
+            ColorSpace::DisplayP3 => [(0.680, 0.320), (0.265, 0.690), (0.150, 0.060)],
+        }
+    }
+
+    /// Returns the matrix converting linear colors in this space to CIE XYZ.
+    ///
+    /// The matrix is derived from the chromaticities of the primaries, scaled
+    /// so that RGB white maps to the D65 white point with a luminance of 1.
+    pub fn rgb_to_xyz(&self) -> ColorMatrix {
+        let to_xyz = |(x, y): (f64, f64)| [x / y, 1.0, (1.0 - x - y) / y];
+        let [r, g, b] = self.primaries().map(to_xyz);
+        let primaries = [[r[0], g[0], b[0]], [r[1], g[1], b[1]], [r[2], g[2], b[2]]];
+
+        let white = to_xyz(D65);
+        let scale = transform(
+            &invert(&primaries),
+            Color::new(white[0], white[1], white[2]),
+        );
+        primaries.map(|row| [row[0] * scale.x(), row[1] * scale.y(), row[2] * scale.z()])
+    }
+
+    /// Returns the matrix converting CIE XYZ to linear colors in this space.
+    pub fn xyz_to_rgb(&self) -> ColorMatrix {
+        invert(&self.rgb_to_xyz())
+    }
+
+    /// Returns the matrix converting linear colors in this space to another.
+    ///
+    /// Spaces with the same primaries convert with the exact identity.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The space to convert to
+    pub fn conversion_to(&self, other: ColorSpace) -> ColorMatrix {
+        match self.primaries() == other.primaries() {
+            true => [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            false => multiply(&other.xyz_to_rgb(), &self.rgb_to_xyz()),
+        }
+    }
+
+    /// Converts a linear color in this space to another.
+    ///
+    /// # Arguments
+    ///
+    /// * `color` - The linear color in this space
+    /// * `other` - The space to convert to
+    ///
+    /// # Returns
+    ///
+    /// The linear color in `other`; components may be negative for colors
+    /// outside its gamut
+    pub fn convert(&self, color: Color, other: ColorSpace) -> Color {
+        transform(&self.conversion_to(other), color)
+    }
+
+    /// Encodes a linear component with the space's transfer function.
+    ///
+    /// # Arguments
+    ///
+    /// * `linear` - The linear component; negative values encode as 0.0
+    ///
+    /// # Returns
+    ///
+    /// The encoded component, in \[0,1\] for linear values in \[0,1\]
+    pub fn encode(&self, linear: f64) -> f64 {
+        let linear = linear.max(0.0);
+        match self {
+            ColorSpace::Srgb | ColorSpace::DisplayP3 => match linear <= 0.003_130_8 {
+                true => 12.92 * linear,
+                false => 1.055 * linear.powf(1.0 / 2.4) - 0.055,
+            },
+            ColorSpace::Rec709 | ColorSpace::Rec2020 => match linear < REC_BETA {
+                true => 4.5 * linear,
+                false => REC_ALPHA * linear.powf(0.45) - (REC_ALPHA - 1.0),
+            },
+        }
+    }
+
+    /// Decodes a component encoded with the space's transfer function.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoded` - The encoded component; negative values decode as 0.0
+    ///
+    /// # Returns
+    ///
+    /// The linear component
+    pub fn decode(&self, encoded: f64) -> f64 {
+        let encoded = encoded.max(0.0);
+        match self {
+            ColorSpace::Srgb | ColorSpace::DisplayP3 => match encoded <= 0.040_45 {
+                true => encoded / 12.92,
+                false => ((encoded + 0.055) / 1.055).powf(2.4),
+            },
+            ColorSpace::Rec709 | ColorSpace::Rec2020 => match encoded < 4.5 * REC_BETA {
+                true => encoded / 4.5,
+                false => ((encoded + REC_ALPHA - 1.0) / REC_ALPHA).powf(1.0 / 0.45),
+            },
+        }
+    }
+
+    /// Converts a color encoded in this space to a linear working-space color.
+    ///
+    /// This is how colors taken from images or picked in other tools, which
+    /// are usually encoded sRGB, should enter a scene.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoded` - The encoded color, with components in \[0,1\]
+    /// * `working` - The working color space of the render
+    ///
+    /// # Returns
+    ///
+    /// The linear color in the working space
+    pub fn decode_color(&self, encoded: Color, working: ColorSpace) -> Color {
+        let linear = Color::new(
+            self.decode(encoded.x()),
+            self.decode(encoded.y()),
+            self.decode(encoded.z()),
+        );
+        self.convert(linear, working)
+    }
+}
+
+/// Multiplies a color by a matrix.
+///
+/// # Arguments
+///
+/// * `matrix` - The matrix, given as rows
+/// * `color` - The color to transform
+pub fn transform(matrix: &ColorMatrix, color: Color) -> Color {
+    let row = |r: &[f64; 3]| r[0] * color.x() + r[1] * color.y() + r[2] * color.z();
+    Color::new(row(&matrix[0]), row(&matrix[1]), row(&matrix[2]))
+}
+
+/// Returns the product of two matrices, which applies `b` and then `a`.
+fn multiply(a: &ColorMatrix, b: &ColorMatrix) -> ColorMatrix {
+    let mut product = [[0.0; 3]; 3];
+    for (row, a_row) in product.iter_mut().zip(a) {
+        for (column, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a_row[k] * b[k][column]).sum();
+        }
+    }
+    product
+}
+
+/// Returns the inverse of a matrix, computed from its cofactors.
+fn invert(m: &ColorMatrix) -> ColorMatrix {
+    let cofactor = |r: usize, c: usize| {
+        let (r0, r1) = ((r + 1) % 3, (r + 2) % 3);
+        let (c0, c1) = ((c + 1) % 3, (c + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    let determinant = (0..3).map(|c| m[0][c] * cofactor(0, c)).sum::<f64>();
+
+    let mut inverse = [[0.0; 3]; 3];
+    for (r, row) in inverse.iter_mut().enumerate() {
+        for (c, value) in row.iter_mut().enumerate() {
+            *value = cofactor(c, r) / determinant;
+        }
+    }
+    inverse
+}
@@ -0,0 +1,166 @@
+//! Homogeneous participating media for the raytracer.
+//!
+//! This module provides a `ConstantMedium` struct that fills the inside of
+//! any closed `Hittable` with a volume of constant density, such as fog or
+//! smoke. Rays passing through the volume scatter at random distances
+//! following an exponential distribution, and scatter according to the
+//! medium's phase function material (usually `Isotropic`).
+
+use std::sync::Arc;
+
+use crate::{
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::{Isotropic, Material},
+    ray::Ray,
+    sampler::Sampler,
+    texture::Texture,
+    vec3::{Color, Vec3},
+};
+
+/// A volume of constant density bounded by a closed hittable.
+///
+/// The boundary must be closed (such as a sphere or a box) so that every
+/// ray entering the volume also leaves it.
+pub struct ConstantMedium {
+    /// The closed surface enclosing the volume
+    boundary: Box<dyn Hittable>,
+    /// The negated inverse of the density, -1 / density
+    neg_inv_density: f64,
+    /// The material describing how light scatters inside the volume
+    phase_function: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    /// Creates a new constant medium with an isotropic phase function of the given color.
+    ///
+    /// # Arguments
+    ///
+    /// * `boundary` - The closed surface enclosing the volume
+    /// * `density` - The scattering density (probability of scattering per unit distance)
+    /// * `albedo` - The color of the scattered light
+    pub fn new(boundary: Box<dyn Hittable>, density: f64, albedo: Color) -> Self {
+        Self::with_phase_function(boundary, density, Arc::new(Isotropic::new(albedo)))
+    }
+
+    /// Creates a new constant medium with an isotropic phase function using a texture.
+    ///
+    /// # Arguments
+    ///
+    /// * `boundary` - The closed surface enclosing the volume
+    /// * `density` - The scattering density (probability of scattering per unit distance)
+    /// * `texture` - The texture giving the color of the scattered light
+    pub fn from_texture(
+        boundary: Box<dyn Hittable>,
+        density: f64,
+        texture: Arc<dyn Texture>,
+    ) -> Self {
+        Self::with_phase_function(
+            boundary,
+            density,
+            Arc::new(Isotropic::from_texture(texture)),
+        )
+    }
+
+    /// Creates a new constant medium with an arbitrary phase function material.
+    ///
+    /// # Arguments
+    ///
+    /// * `boundary` - The closed surface enclosing the volume
+    /// * `density` - The scattering density (probability of scattering per unit distance)
+    /// * `phase_function` - The material used at scattering events inside the volume
+    pub fn with_phase_function(
+        boundary: Box<dyn Hittable>,
+        density: f64,
+        phase_function: Arc<dyn Material>,
+    ) -> Self {
+        Self {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function,
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    /// Determines if a ray scatters inside the volume.
+    ///
+    /// The entry and exit points of the ray are found on the boundary, and
+    /// a scattering distance is sampled from the exponential distribution.
+    /// If that distance lies before the exit point, the ray scatters there;
+    /// otherwise it passes through the volume unaffected.
+    ///
+    /// # Arguments
+    ///
+    /// * `r` - The ray to test for intersection
+    /// * `ray_t` - The interval along the ray to check for intersection
+    /// * `sampler` - The source of random numbers for the scattering distance
+    ///
+    /// # Returns
+    ///
+    /// A `HitRecord` at the scattering point, or `None` if the ray passes through
+    fn hit(&self, r: &Ray, ray_t: Interval, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        let mut rec1 = self.boundary.hit(r, Interval::universe(), sampler)?;
+        let mut rec2 =
+            self.boundary
+                .hit(r, Interval::new(rec1.t + 0.0001, f64::INFINITY), sampler)?;
+
+        rec1.t = f64::max(rec1.t, ray_t.min);
+        rec2.t = f64::min(rec2.t, ray_t.max);
+
+        if rec1.t >= rec2.t {
+            return None;
+        }
+
+        rec1.t = f64::max(rec1.t, 0.0);
+
+        let ray_length = r.direction().length();
+        let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
+        let hit_distance = self.neg_inv_density * f64::ln(sampler.get_1d());
+
+        if hit_distance > distance_inside_boundary {
+            return None;
+        }
+
+        let t = rec1.t + hit_distance / ray_length;
+
+        Some(HitRecord {
+            p: r.at(t),
+            // The normal and face are arbitrary inside a volume.
+            normal: Vec3::new(1.0, 0.0, 0.0),
+            mat: Some(self.phase_function.clone()),
+            t,
+            u: 0.0,
+            v: 0.0,
+            front_face: true,
+            in_medium: true,
+        })
+    }
+
+    /// Computes the transmittance of the volume analytically with the Beer-Lambert law.
+    fn transmittance(&self, r: &Ray, ray_t: Interval, sampler: &mut dyn Sampler) -> f64 {
+        let Some(rec1) = self.boundary.hit(r, Interval::universe(), sampler) else {
+            return 1.0;
+        };
+        let Some(rec2) =
+            self.boundary
+                .hit(r, Interval::new(rec1.t + 0.0001, f64::INFINITY), sampler)
+        else {
+            return 1.0;
+        };
+
+        let t1 = f64::max(f64::max(rec1.t, ray_t.min), 0.0);
+        let t2 = f64::min(rec2.t, ray_t.max);
+        if t1 >= t2 {
+            return 1.0;
+        }
+
+        let distance_inside_boundary = (t2 - t1) * r.direction().length();
+        f64::exp(distance_inside_boundary / self.neg_inv_density)
+    }
+
+    /// Appends the phase function; the boundary's materials are never hit.
+    fn collect_materials(&self, materials: &mut Vec<Arc<dyn Material>>) {
+        materials.push(self.phase_function.clone());
+    }
+}
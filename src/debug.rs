@@ -0,0 +1,278 @@
+//! Diagnostic integrators for the raytracer.
+//!
+//! This module provides integrators that visualize scene data instead of
+//! simulating light transport. They are rendered through the same `Camera`
+//! pipeline as the physically-based integrators, which makes them useful for
+//! checking geometry, texture coordinates and material assignments:
+//! - `AmbientOcclusion`: Fraction of the hemisphere that is not blocked nearby
+//! - `DebugIntegrator`: False-color views of hit data, chosen with `DebugMode`
+
+use std::sync::Arc;
+
+use crate::{
+    hittable::Hittable,
+    integrator::Integrator,
+    interval::Interval,
+    material::{Material, Scatter},
+    ray::Ray,
+    sampler::Sampler,
+    vec3::{Color, Vec3},
+};
+
+/// An ambient occlusion integrator.
+///
+/// At the first hit, cosine-weighted directions are sampled over the
+/// hemisphere around the normal, and the pixel is shaded by the fraction of
+/// them that do not hit anything within `radius`. Open areas are white and
+/// creases and contact points are dark. Rays that miss the scene are white.
+#[derive(Debug, Clone, Copy)]
+pub struct AmbientOcclusion {
+    /// The distance within which geometry occludes a point
+    pub radius: f64,
+    /// The number of occlusion rays traced per camera ray
+    pub samples: u32,
+}
+
+impl AmbientOcclusion {
+    /// Creates a new ambient occlusion integrator.
+    ///
+    /// # Arguments
+    ///
+    /// * `radius` - The distance within which geometry occludes a point
+    /// * `samples` - The number of occlusion rays traced per camera ray
+    pub fn new(radius: f64, samples: u32) -> Self {
+        Self { radius, samples }
+    }
+}
+
+impl Default for AmbientOcclusion {
+    /// Creates an ambient occlusion integrator with a radius of 1 and a single occlusion ray.
+    fn default() -> Self {
+        Self::new(1.0, 1)
+    }
+}
+
+impl Integrator for AmbientOcclusion {
+    /// Computes the unoccluded fraction of the hemisphere at the first hit.
+    fn ray_color(
+        &self,
+        r: &Ray,
+        _depth: u32,
+        world: &dyn Hittable,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let Some(rec) = world.hit(r, Interval::new(0.001, f64::INFINITY), sampler) else {
+            return Color::new(1.0, 1.0, 1.0);
+        };
+
+        let samples = self.samples.max(1);
+        let mut unoccluded = 0;
+        for _ in 0..samples {
+            let mut direction = rec.normal + Vec3::random_unit_vector(sampler);
+            if direction.near_zero() {
+                direction = rec.normal;
+            }
+
+            let occlusion_ray = Ray::new(rec.p, direction.unit_vector());
+            if world
+                .hit(&occlusion_ray, Interval::new(0.001, self.radius), sampler)
+                .is_none()
+            {
+                unoccluded += 1;
+            }
+        }
+
+        let visibility = unoccluded as f64 / samples as f64;
+        Color::new(visibility, visibility, visibility)
+    }
+
+    /// Names the integrator and its occlusion settings.
+    fn description(&self) -> String {
+        format!(
+            "ambient occlusion, radius {}, {} samples",
+            self.radius, self.samples
+        )
+    }
+}
+
+/// The quantity visualized by a `DebugIntegrator`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DebugMode {
+    /// The normal used for shading, which always faces the incoming ray,
+    /// mapped from \[-1,1\] to \[0,1\]
+    ShadingNormal,
+    /// The outward-facing geometric normal of the surface, mapped from
+    /// \[-1,1\] to \[0,1\]; back faces show the opposite color to `ShadingNormal`
+    GeometricNormal,
+    /// The surface coordinates, with u in red and v in green
+    Uv,
+    /// The distance to the first hit, white up close and fading to black at
+    /// the given maximum distance
+    HitDistance(f64),
+    /// A distinct color for every material instance, derived from the
+    /// order in which the scene lists its materials so that it is the same
+    /// from one render to the next
+    MaterialId,
+    /// The number of bounces before the path ended, as a heatmap from blue
+    /// (none) to red (the maximum depth)
+    BounceCount,
+}
+
+/// An integrator that shows hit data as false colors.
+///
+/// Rays that miss the scene are black, except in `BounceCount` mode where
+/// they count as zero bounces. In `MaterialId` mode, materials the scene
+/// does not list through `Hittable::collect_materials` are black.
+#[derive(Debug, Clone)]
+pub struct DebugIntegrator {
+    /// The quantity to visualize
+    pub mode: DebugMode,
+    /// The addresses of the scene's distinct materials, in scene order,
+    /// gathered by `preprocess` so that each material is identified by its
+    /// index rather than by where it happens to be allocated
+    materials: Vec<usize>,
+}
+
+impl DebugIntegrator {
+    /// Creates a new debug integrator.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - The quantity to visualize
+    pub fn new(mode: DebugMode) -> Self {
+        Self {
+            mode,
+            materials: Vec::new(),
+        }
+    }
+
+    /// Returns the address of a material, which identifies the instance.
+    fn address(mat: &Arc<dyn Material>) -> usize {
+        Arc::as_ptr(mat) as *const () as usize
+    }
+
+    /// Counts the bounces of a path traced with the scene's materials.
+    fn bounce_count(r: &Ray, depth: u32, world: &dyn Hittable, sampler: &mut dyn Sampler) -> u32 {
+        let mut ray = *r;
+        for bounces in 0..depth {
+            let Some(rec) = world.hit(&ray, Interval::new(0.001, f64::INFINITY), sampler) else {
+                return bounces;
+            };
+
+            let mut scattered = Ray::default();
+            let mut attenuation = Color::default();
+            if rec.mat.as_ref().unwrap().scatter(
+                &ray,
+                &rec,
+                &mut attenuation,
+                &mut scattered,
+                sampler,
+            ) == Scatter::Absorbed
+            {
+                return bounces + 1;
+            }
+            ray = scattered;
+        }
+
+        depth
+    }
+}
+
+impl Integrator for DebugIntegrator {
+    /// Numbers the distinct materials of the scene in the order it lists them.
+    fn preprocess(&mut self, world: &dyn Hittable, _seed: u64) {
+        let mut materials = Vec::new();
+        world.collect_materials(&mut materials);
+
+        self.materials.clear();
+        for mat in &materials {
+            let address = Self::address(mat);
+            if !self.materials.contains(&address) {
+                self.materials.push(address);
+            }
+        }
+    }
+
+    /// Computes the false color of the selected quantity along the ray.
+    fn ray_color(
+        &self,
+        r: &Ray,
+        depth: u32,
+        world: &dyn Hittable,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        if self.mode == DebugMode::BounceCount {
+            let bounces = Self::bounce_count(r, depth, world, sampler);
+            return heatmap(bounces as f64 / depth.max(1) as f64);
+        }
+
+        let Some(rec) = world.hit(r, Interval::new(0.001, f64::INFINITY), sampler) else {
+            return Color::default();
+        };
+
+        match self.mode {
+            DebugMode::ShadingNormal => 0.5 * (rec.normal + Color::new(1.0, 1.0, 1.0)),
+            DebugMode::GeometricNormal => {
+                let outward_normal = match rec.front_face {
+                    true => rec.normal,
+                    false => -rec.normal,
+                };
+                0.5 * (outward_normal + Color::new(1.0, 1.0, 1.0))
+            }
+            DebugMode::Uv => Color::new(rec.u, rec.v, 0.0),
+            DebugMode::HitDistance(max_distance) => {
+                let distance = rec.t * r.direction().length();
+                let shade = (1.0 - distance / max_distance).clamp(0.0, 1.0);
+                Color::new(shade, shade, shade)
+            }
+            DebugMode::MaterialId => {
+                let address = rec.mat.as_ref().map(Self::address);
+                match self.materials.iter().position(|&m| Some(m) == address) {
+                    Some(index) => id_color(index as u64),
+                    None => Color::default(),
+                }
+            }
+            DebugMode::BounceCount => unreachable!(),
+        }
+    }
+
+    /// Names the integrator and the quantity it shows.
+    fn description(&self) -> String {
+        format!("debug view {:?}", self.mode)
+    }
+}
+
+/// Maps a value in \[0,1\] to a heatmap color.
+///
+/// The ramp goes from blue through cyan, green and yellow to red.
+///
+/// # Arguments
+///
+/// * `t` - The value to map; values outside \[0,1\] are clamped
+///
+/// # Returns
+///
+/// The heatmap color
+pub fn heatmap(t: f64) -> Color {
+    let t = t.clamp(0.0, 1.0) * 4.0;
+    match t {
+        t if t < 1.0 => Color::new(0.0, t, 1.0),
+        t if t < 2.0 => Color::new(0.0, 1.0, 2.0 - t),
+        t if t < 3.0 => Color::new(t - 2.0, 1.0, 0.0),
+        t => Color::new(1.0, 4.0 - t, 0.0),
+    }
+}
+
+/// Maps an identifier to a bright, well-spread color.
+///
+/// The identifier is scrambled with the SplitMix64 finalizer so that nearby
+/// values (such as consecutive material indices) get unrelated colors.
+fn id_color(id: u64) -> Color {
+    let mut z = id.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+
+    let channel = |shift: u32| 0.2 + 0.8 * ((z >> shift) & 0xff) as f64 / 255.0;
+    Color::new(channel(0), channel(8), channel(16))
+}
@@ -0,0 +1,437 @@
+//! Distributed rendering over TCP.
+//!
+//! A coordinator splits the image into the camera's tiles and hands them
+//! out to worker processes, which may run on other machines or on the same
+//! one. This module provides:
+//! - `coordinate`: Serves tiles to workers and collects their results
+//! - `work`: Connects to a coordinator and renders the tiles it sends
+//!
+//! Every worker first receives the camera settings and a description of
+//! the scene. A tile is rendered by a single thread, so a worker opens one
+//! connection per thread and renders a tile on each. A connection that
+//! drops or hangs has its unfinished tile handed to another one.
+//! Tiles are rendered with the same per-pixel random numbers as a local
+//! render and their results are added to the film in tile order, so the
+//! image is the same as a single-pass local render with the same settings,
+//! however many workers take part.
+//!
+//! Scenes are sent as a `SceneDescription`, so only scenes made of the
+//! spheres, media and constant-parameter materials it can describe can be
+//! rendered this way; textured materials, grid volumes and custom
+//! primitives need a local render.
+//!
+//! All messages are a little-endian `u32` length followed by that many
+//! bytes, the first of which gives the kind of message.
+
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    camera::{Camera, ColorMode},
+    film::{Film, FilmPixel, read_f64, read_u32, read_u64, read_vec3, write_vec3},
+    filter::{Filter, FilterTable},
+    hittable::Hittable,
+    integrator::Splat,
+    scene::SceneDescription,
+    tile::{Tile, tiles},
+};
+
+/// A message with the camera settings and the scene, sent to new workers.
+const SETUP: u8 = 0;
+/// A message with a tile to render.
+const TILE: u8 = 1;
+/// A message telling a worker that the image is done.
+const FINISH: u8 = 2;
+/// A message with the samples and splats of a rendered tile.
+const TILE_DONE: u8 = 3;
+
+/// The largest message accepted, which guards against corrupt lengths.
+const MAX_MESSAGE_LEN: u32 = 1 << 30;
+
+/// How long idle threads wait before checking for new work.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long a worker may take to render a tile before it is considered hung.
+///
+/// The connection is then dropped and the tile handed to another worker.
+const TILE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// The samples and splats of a rendered tile.
+type TileResult = (Vec<FilmPixel>, Vec<Splat>);
+
+/// The tiles of a distributed render, shared by the threads serving workers.
+struct Schedule {
+    /// The tiles of the image
+    tiles: Vec<Tile>,
+    /// The indices of the tiles waiting for a worker
+    queue: Mutex<VecDeque<usize>>,
+    /// The results of the finished tiles, by tile index
+    results: Mutex<Vec<Option<TileResult>>>,
+    /// The number of tiles not finished yet
+    remaining: AtomicUsize,
+}
+
+/// Renders an image with the workers that connect to a listener.
+///
+/// The camera's settings and the scene are sent to every worker, along
+/// with the camera's tiles. The integrator and sampler are not sent:
+/// workers use those of the camera they were started with, which should
+/// match the coordinator's. Progressive passes and checkpoints are not used.
+///
+/// Workers may connect at any time until the image is done. If no worker
+/// is connected, the coordinator waits for one.
+///
+/// # Arguments
+///
+/// * `listener` - The listener workers connect to
+/// * `camera` - The camera settings of the render
+/// * `scene` - The scene to render
+///
+/// # Returns
+///
+/// The film holding the samples and splats of every pixel, or an error if
+/// accepting connections fails
+pub fn coordinate(
+    listener: &TcpListener,
+    camera: &Camera,
+    scene: &SceneDescription,
+) -> io::Result<Film> {
+    let width = camera.image_width;
+    let height = camera.image_height();
+
+    let mut setup = vec![SETUP];
+    write_camera(&mut setup, camera)?;
+    scene.write_to(&mut setup)?;
+
+    let tiles = tiles(width, height, camera.tile_size, camera.tile_order);
+    let schedule = Schedule {
+        queue: Mutex::new((0..tiles.len()).collect()),
+        results: Mutex::new((0..tiles.len()).map(|_| None).collect()),
+        remaining: AtomicUsize::new(tiles.len()),
+        tiles,
+    };
+
+    listener.set_nonblocking(true)?;
+    thread::scope(|scope| {
+        while schedule.remaining.load(Ordering::SeqCst) > 0 {
+            match listener.accept() {
+                Ok((stream, address)) => {
+                    eprintln!("Worker connected from {}", address);
+                    let (schedule, setup) = (&schedule, &setup);
+                    scope.spawn(move || serve_worker(stream, setup, schedule));
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    })?;
+    eprintln!();
+
+    // Results are added in tile order so the film does not depend on which
+    // worker rendered which tile.
+    let filter = FilterTable::new(camera.filter, camera.filter_radius);
+    let mut film = Film::with_filter(width, height, filter);
+    let results = schedule.results.into_inner().unwrap();
+    for (tile, result) in schedule.tiles.iter().zip(results) {
+        let (pixels, splats) = result.expect("Every tile is finished");
+        for ((i, j), pixel) in tile.pixels().zip(pixels) {
+            *film.pixel_mut(i, j) = pixel;
+        }
+        for splat in splats {
+            film.add_splat(splat.x, splat.y, splat.color);
+        }
+    }
+
+    Ok(film)
+}
+
+/// Hands tiles to one worker until the image is done or the worker disconnects.
+///
+/// A tile the worker was rendering when it disconnected, or did not finish
+/// within `TILE_TIMEOUT`, goes back to the front of the queue.
+///
+/// # Arguments
+///
+/// * `stream` - The connection to the worker
+/// * `setup` - The setup message sent to every worker
+/// * `schedule` - The tiles of the render
+fn serve_worker(mut stream: TcpStream, setup: &[u8], schedule: &Schedule) {
+    let address = stream
+        .peer_addr()
+        .map_or("unknown address".to_string(), |a| a.to_string());
+    let mut in_flight = None;
+
+    let result = (|| -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(TILE_TIMEOUT))?;
+        stream.set_write_timeout(Some(TILE_TIMEOUT))?;
+        write_message(&mut stream, setup)?;
+
+        loop {
+            let next = schedule.queue.lock().unwrap().pop_front();
+            let Some(index) = next else {
+                if schedule.remaining.load(Ordering::SeqCst) == 0 {
+                    return write_message(&mut stream, &[FINISH]);
+                }
+                // Another worker may still disconnect and leave a tile behind.
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            };
+            in_flight = Some(index);
+
+            let tile = &schedule.tiles[index];
+            let mut message = vec![TILE];
+            for value in [index as u32, tile.x, tile.y, tile.width, tile.height] {
+                message.extend_from_slice(&value.to_le_bytes());
+            }
+            write_message(&mut stream, &message)?;
+
+            let result = read_tile_done(&read_message(&mut stream)?, index, tile)?;
+            schedule.results.lock().unwrap()[index] = Some(result);
+            in_flight = None;
+            let remaining = schedule.remaining.fetch_sub(1, Ordering::SeqCst) - 1;
+            eprint!("\rTiles remaining: {} ", remaining);
+        }
+    })();
+
+    if let Err(e) = result {
+        eprintln!("\nWorker {} disconnected: {}", address, e);
+        if let Some(index) = in_flight {
+            schedule.queue.lock().unwrap().push_front(index);
+        }
+    }
+}
+
+/// Parses the result of a tile sent by a worker.
+///
+/// # Arguments
+///
+/// * `message` - The message received from the worker
+/// * `index` - The index of the tile the worker was sent
+/// * `tile` - The tile the worker was sent
+///
+/// # Returns
+///
+/// The samples and splats of the tile, or an error if the message is not
+/// the result of that tile
+fn read_tile_done(message: &[u8], index: usize, tile: &Tile) -> io::Result<TileResult> {
+    let mut input = message;
+    let mut kind = [0];
+    input.read_exact(&mut kind)?;
+    if kind[0] != TILE_DONE || read_u32(&mut input)? != index as u32 {
+        return Err(invalid_data("expected the result of the tile sent"));
+    }
+
+    let pixels = (0..tile.width * tile.height)
+        .map(|_| FilmPixel::read_from(&mut input))
+        .collect::<io::Result<_>>()?;
+    let splat_count = read_u32(&mut input)?;
+    let splats = (0..splat_count)
+        .map(|_| {
+            Ok(Splat {
+                x: read_f64(&mut input)?,
+                y: read_f64(&mut input)?,
+                color: read_vec3(&mut input)?,
+            })
+        })
+        .collect::<io::Result<_>>()?;
+    Ok((pixels, splats))
+}
+
+/// Renders the tiles sent by a coordinator until it reports the image done.
+///
+/// The settings sent by the coordinator replace those of the camera; the
+/// camera's integrator and sampler are kept. Tiles are rendered on a single
+/// thread each, so the worker opens a connection for every thread of the
+/// rayon pool and renders a tile on each of them at a time.
+///
+/// # Arguments
+///
+/// * `address` - The address of the coordinator
+/// * `camera` - The camera providing the integrator and sampler
+///
+/// # Returns
+///
+/// An error if the first connection fails or the coordinator sends an
+/// invalid message over it
+pub fn work<A: ToSocketAddrs>(address: A, mut camera: Camera) -> io::Result<()> {
+    let addresses: Vec<SocketAddr> = address.to_socket_addrs()?.collect();
+    let mut stream = connect(&addresses[..])?;
+    let setup = read_setup(&mut stream)?;
+    let mut input = &setup[..];
+    read_camera(&mut input, &mut camera)?;
+    let world = SceneDescription::read_from(&mut input)?.build();
+    camera.prepare(&world);
+
+    let (camera, world) = (&camera, &world);
+    thread::scope(|scope| {
+        let others: Vec<_> = (1..rayon::current_num_threads())
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut stream = connect(&addresses[..])?;
+                    // Every connection is sent the same settings and scene.
+                    read_setup(&mut stream)?;
+                    render_tiles(stream, camera, world)
+                })
+            })
+            .collect();
+
+        let result = render_tiles(stream, camera, world);
+        // The tiles of a connection that fails are rendered over the others,
+        // so only the first connection decides the outcome.
+        for other in others {
+            if let Err(e) = other.join().expect("Worker thread panicked") {
+                eprintln!("Connection to the coordinator failed: {}", e);
+            }
+        }
+        result
+    })
+}
+
+/// Connects to a coordinator.
+fn connect(addresses: &[SocketAddr]) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect(addresses)?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+/// Reads the setup message a coordinator sends first on every connection.
+///
+/// # Returns
+///
+/// The camera settings and scene that follow the kind of the message
+fn read_setup(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let mut setup = read_message(stream)?;
+    match setup.first() {
+        Some(&SETUP) => Ok(setup.split_off(1)),
+        _ => Err(invalid_data("expected the setup message")),
+    }
+}
+
+/// Renders the tiles sent over one connection until the coordinator reports the image done.
+///
+/// # Arguments
+///
+/// * `stream` - The connection to the coordinator, past the setup message
+/// * `camera` - The camera, prepared for the scene
+/// * `world` - The scene to render
+fn render_tiles(mut stream: TcpStream, camera: &Camera, world: &dyn Hittable) -> io::Result<()> {
+    let mut kind = [0];
+    loop {
+        let message = read_message(&mut stream)?;
+        let mut input = &message[..];
+        input.read_exact(&mut kind)?;
+        match kind[0] {
+            TILE => {
+                let index = read_u32(&mut input)?;
+                let tile = Tile {
+                    x: read_u32(&mut input)?,
+                    y: read_u32(&mut input)?,
+                    width: read_u32(&mut input)?,
+                    height: read_u32(&mut input)?,
+                };
+
+                let mut pixels = vec![FilmPixel::default(); tile.pixels().count()];
+                let splats =
+                    camera.render_tile(world, &tile, &mut pixels, 0..camera.samples_per_pixel);
+
+                let mut reply = vec![TILE_DONE];
+                reply.extend_from_slice(&index.to_le_bytes());
+                for pixel in &pixels {
+                    pixel.write_to(&mut reply)?;
+                }
+                reply.extend_from_slice(&(splats.len() as u32).to_le_bytes());
+                for splat in &splats {
+                    reply.extend_from_slice(&splat.x.to_le_bytes());
+                    reply.extend_from_slice(&splat.y.to_le_bytes());
+                    write_vec3(&mut reply, &splat.color)?;
+                }
+                write_message(&mut stream, &reply)?;
+            }
+            FINISH => return Ok(()),
+            _ => return Err(invalid_data("unexpected message from the coordinator")),
+        }
+    }
+}
+
+/// Writes a length-prefixed message.
+fn write_message<W: Write>(out: &mut W, message: &[u8]) -> io::Result<()> {
+    let mut framed = Vec::with_capacity(4 + message.len());
+    framed.extend_from_slice(&(message.len() as u32).to_le_bytes());
+    framed.extend_from_slice(message);
+    out.write_all(&framed)?;
+    out.flush()
+}
+
+/// Reads a length-prefixed message.
+fn read_message<R: Read>(input: &mut R) -> io::Result<Vec<u8>> {
+    let len = read_u32(input)?;
+    if len == 0 || len > MAX_MESSAGE_LEN {
+        return Err(invalid_data("invalid message length"));
+    }
+
+    let mut message = vec![0; len as usize];
+    input.read_exact(&mut message)?;
+    Ok(message)
+}
+
+/// Writes the settings of a camera that affect the rendered samples.
+fn write_camera<W: Write>(out: &mut W, camera: &Camera) -> io::Result<()> {
+    for value in [camera.aspect_ratio, camera.adaptive_threshold, camera.vfov] {
+        out.write_all(&value.to_le_bytes())?;
+    }
+    for value in [
+        camera.image_width,
+        camera.samples_per_pixel,
+        camera.min_samples_per_pixel,
+        camera.max_depth,
+    ] {
+        out.write_all(&value.to_le_bytes())?;
+    }
+    for v in [&camera.lookfrom, &camera.lookat, &camera.vup] {
+        write_vec3(out, v)?;
+    }
+    out.write_all(&camera.defocus_angle.to_le_bytes())?;
+    out.write_all(&camera.focus_dist.to_le_bytes())?;
+    camera.color_mode.write_to(out)?;
+    out.write_all(&camera.seed.to_le_bytes())?;
+    camera.filter.write_to(out)?;
+    out.write_all(&camera.filter_radius.to_le_bytes())
+}
+
+/// Reads settings written by `write_camera` into a camera.
+fn read_camera<R: Read>(input: &mut R, camera: &mut Camera) -> io::Result<()> {
+    camera.aspect_ratio = read_f64(input)?;
+    camera.adaptive_threshold = read_f64(input)?;
+    camera.vfov = read_f64(input)?;
+    camera.image_width = read_u32(input)?;
+    camera.samples_per_pixel = read_u32(input)?;
+    camera.min_samples_per_pixel = read_u32(input)?;
+    camera.max_depth = read_u32(input)?;
+    camera.lookfrom = read_vec3(input)?;
+    camera.lookat = read_vec3(input)?;
+    camera.vup = read_vec3(input)?;
+    camera.defocus_angle = read_f64(input)?;
+    camera.focus_dist = read_f64(input)?;
+    camera.color_mode = ColorMode::read_from(input)?;
+    camera.seed = read_u64(input)?;
+    camera.filter = Filter::read_from(input)?;
+    camera.filter_radius = read_f64(input)?;
+    Ok(())
+}
+
+/// Returns an error for malformed data received from the other end.
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
@@ -0,0 +1,455 @@
+//! Image accumulation for the raytracer.
+//!
+//! This module provides the `Film` the camera records its samples on:
+//! - `Film`: The pixels of an image, along with the splats made on it
+//! - `FilmPixel`: The running weighted mean and variance of one pixel's samples
+//!
+//! Samples are weighted by the camera's reconstruction filter, and each
+//! pixel is the weighted mean of its own samples. Splats are spread over
+//! the pixels around them by the same filter.
+//!
+//! Every pixel keeps its own sample count, so pixels can take different
+//! numbers of samples. The variance of each pixel's luminance gives an
+//! estimate of its remaining error, which adaptive sampling uses to stop
+//! sampling pixels that have converged.
+//!
+//! A film can be written to and read back from a byte stream exactly, which
+//! lets long renders be checkpointed and resumed, and films rendered
+//! separately can be merged into one.
+
+use std::io::{self, Read, Write};
+
+use crate::{
+    filter::{Filter, FilterTable},
+    vec3::{Color, Vec3},
+};
+
+/// The most pixels reserved before any are read, so a corrupt header cannot
+/// make loading allocate more than the stream holds.
+const MAX_PREALLOCATED_PIXELS: usize = 1 << 20;
+
+/// The samples recorded for one pixel.
+///
+/// The luminance of the weighted samples is tracked with Welford's
+/// algorithm, which keeps the variance accurate even after many samples.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FilmPixel {
+    /// The sum of the sample colors, each multiplied by its filter weight
+    pub sum: Color,
+    /// The sum of the samples' filter weights
+    pub weight_sum: f64,
+    /// The number of samples taken
+    pub samples: u32,
+    /// The mean luminance of the weighted samples
+    luminance_mean: f64,
+    /// The sum of squared differences of the weighted samples' luminance from the mean
+    luminance_m2: f64,
+}
+
+impl FilmPixel {
+    /// Records a sample.
+    ///
+    /// # Arguments
+    ///
+    /// * `color` - The color of the sample
+    /// * `weight` - The filter weight of the sample
+    pub fn add_sample(&mut self, color: Color, weight: f64) {
+        let color = weight * color;
+        self.sum += color;
+        self.weight_sum += weight;
+        self.samples += 1;
+
+        let luminance = color.luminance();
+        let delta = luminance - self.luminance_mean;
+        self.luminance_mean += delta / self.samples as f64;
+        self.luminance_m2 += delta * (luminance - self.luminance_mean);
+    }
+
+    /// Adds the samples recorded in another pixel.
+    ///
+    /// The luminance statistics are combined with the parallel form of
+    /// Welford's algorithm, so the result is the same as if every sample had
+    /// been recorded in this pixel.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The pixel whose samples are added
+    pub fn merge(&mut self, other: &FilmPixel) {
+        let samples = self.samples + other.samples;
+        if samples == 0 {
+            return;
+        }
+
+        let (n_a, n_b) = (self.samples as f64, other.samples as f64);
+        let delta = other.luminance_mean - self.luminance_mean;
+        self.luminance_mean += delta * n_b / samples as f64;
+        self.luminance_m2 += other.luminance_m2 + delta * delta * n_a * n_b / samples as f64;
+        self.sum += other.sum;
+        self.weight_sum += other.weight_sum;
+        self.samples = samples;
+    }
+
+    /// Returns the weighted mean color of the samples, or black if their
+    /// weights sum to zero.
+    pub fn mean(&self) -> Color {
+        match self.weight_sum == 0.0 {
+            true => Color::default(),
+            false => self.sum / self.weight_sum,
+        }
+    }
+
+    /// Returns the sample variance of the luminance, or 0.0 for fewer than two samples.
+    pub fn variance(&self) -> f64 {
+        match self.samples < 2 {
+            true => 0.0,
+            false => self.luminance_m2 / (self.samples - 1) as f64,
+        }
+    }
+
+    /// Writes the exact bits of the pixel to a byte stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `out` - The stream to write to
+    ///
+    /// # Returns
+    ///
+    /// An error if writing fails
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write_vec3(out, &self.sum)?;
+        out.write_all(&self.weight_sum.to_le_bytes())?;
+        out.write_all(&self.samples.to_le_bytes())?;
+        out.write_all(&self.luminance_mean.to_le_bytes())?;
+        out.write_all(&self.luminance_m2.to_le_bytes())
+    }
+
+    /// Reads a pixel written by `write_to` from a byte stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The stream to read from
+    ///
+    /// # Returns
+    ///
+    /// The pixel, or an error if reading fails
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            sum: read_vec3(input)?,
+            weight_sum: read_f64(input)?,
+            samples: read_u32(input)?,
+            luminance_mean: read_f64(input)?,
+            luminance_m2: read_f64(input)?,
+        })
+    }
+
+    /// Estimates the error of the pixel as it is displayed.
+    ///
+    /// This is how much the gamma-encoded luminance would change if the
+    /// mean were off by one standard error. Working on the displayed value
+    /// makes the same error equally visible in dark and bright pixels.
+    ///
+    /// A pixel whose samples are all black has no variance, but it may be
+    /// lit by paths that are merely rare, so it is never considered accurate.
+    ///
+    /// # Returns
+    ///
+    /// The estimated error, or infinity for fewer than two samples or a
+    /// black mean
+    pub fn error(&self) -> f64 {
+        if self.samples < 2 || self.luminance_mean <= 0.0 {
+            return f64::INFINITY;
+        }
+
+        let standard_error = (self.variance() / self.samples as f64).sqrt();
+        let mean = self.luminance_mean;
+        (mean + standard_error).sqrt() - mean.sqrt()
+    }
+}
+
+/// The pixels of an image being rendered.
+///
+/// Splats, the contributions of light paths that reach the camera from
+/// elsewhere, are kept apart from the pixel samples: every camera sample
+/// may splat anywhere on the image, so they are averaged over the samples
+/// of the whole image rather than those of their pixel.
+#[derive(Debug, Clone)]
+pub struct Film {
+    /// The width of the image in pixels
+    width: u32,
+    /// The height of the image in pixels
+    height: u32,
+    /// The reconstruction filter splats are spread with
+    filter: FilterTable,
+    /// The samples of every pixel, indexed by `j * width + i`
+    pixels: Vec<FilmPixel>,
+    /// The sum of the splats on every pixel, indexed by `j * width + i`
+    splats: Vec<Color>,
+}
+
+impl Film {
+    /// Creates an empty film with a box filter of half a pixel, which adds
+    /// every splat to the pixel it lands in only.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the image in pixels
+    /// * `height` - The height of the image in pixels
+    pub fn new(width: u32, height: u32) -> Self {
+        Self::with_filter(width, height, FilterTable::default())
+    }
+
+    /// Creates an empty film that spreads splats with a reconstruction filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the image in pixels
+    /// * `height` - The height of the image in pixels
+    /// * `filter` - The filter splats are spread with
+    pub fn with_filter(width: u32, height: u32, filter: FilterTable) -> Self {
+        let pixels = width as usize * height as usize;
+        Self {
+            width,
+            height,
+            filter,
+            pixels: vec![FilmPixel::default(); pixels],
+            splats: vec![Color::default(); pixels],
+        }
+    }
+
+    /// Returns the width of the image in pixels
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the height of the image in pixels
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the reconstruction filter splats are spread with
+    pub fn filter(&self) -> &FilterTable {
+        &self.filter
+    }
+
+    /// Returns the samples of a pixel.
+    ///
+    /// # Arguments
+    ///
+    /// * `i` - The horizontal coordinate of the pixel
+    /// * `j` - The vertical coordinate of the pixel
+    pub fn pixel(&self, i: u32, j: u32) -> &FilmPixel {
+        &self.pixels[self.index(i, j)]
+    }
+
+    /// Returns the samples of a pixel for recording new ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `i` - The horizontal coordinate of the pixel
+    /// * `j` - The vertical coordinate of the pixel
+    pub fn pixel_mut(&mut self, i: u32, j: u32) -> &mut FilmPixel {
+        let index = self.index(i, j);
+        &mut self.pixels[index]
+    }
+
+    /// Returns the samples of every pixel, indexed by `j * width + i`
+    pub fn pixels(&self) -> &[FilmPixel] {
+        &self.pixels
+    }
+
+    /// Spreads a splat over the pixels around it.
+    ///
+    /// Every pixel whose center lies within the filter's radius of the
+    /// splat receives the splat weighted by the filter, normalized so that
+    /// the weights integrate to 1 over the image plane.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The horizontal film coordinate of the splat, where pixel `i` covers \[i,i+1)
+    /// * `y` - The vertical film coordinate of the splat, where pixel `j` covers \[j,j+1)
+    /// * `color` - The color of the splat
+    pub fn add_splat(&mut self, x: f64, y: f64, color: Color) {
+        let radius = self.filter.radius();
+        let i_min = (x - 0.5 - radius).ceil().max(0.0) as u32;
+        let i_max = (x - 0.5 + radius).floor().min(self.width as f64 - 1.0);
+        let j_min = (y - 0.5 - radius).ceil().max(0.0) as u32;
+        let j_max = (y - 0.5 + radius).floor().min(self.height as f64 - 1.0);
+        if i_max < 0.0 || j_max < 0.0 {
+            return;
+        }
+
+        for j in j_min..=j_max as u32 {
+            for i in i_min..=i_max as u32 {
+                let weight = self.filter.evaluate(i as f64 + 0.5 - x, j as f64 + 0.5 - y);
+                if weight != 0.0 {
+                    let index = self.index(i, j);
+                    self.splats[index] += weight * color;
+                }
+            }
+        }
+    }
+
+    /// Adds the samples and splats of another film of the same size.
+    ///
+    /// Each pixel of the merged film is the mean of the samples of both
+    /// films, so films rendered with different seeds are averaged weighted
+    /// by their sample counts, and the splats of both are averaged over the
+    /// samples of both.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The film whose samples are added
+    ///
+    /// # Panics
+    ///
+    /// If the films differ in size
+    pub fn merge(&mut self, other: &Film) {
+        assert!(
+            self.width == other.width && self.height == other.height,
+            "Cannot merge a {}x{} film into a {}x{} film",
+            other.width,
+            other.height,
+            self.width,
+            self.height
+        );
+
+        for (pixel, other_pixel) in self.pixels.iter_mut().zip(&other.pixels) {
+            pixel.merge(other_pixel);
+        }
+        for (splat, other_splat) in self.splats.iter_mut().zip(&other.splats) {
+            *splat += *other_splat;
+        }
+    }
+
+    /// Returns the number of samples taken over the whole image
+    pub fn total_samples(&self) -> u64 {
+        self.pixels.iter().map(|p| p.samples as u64).sum()
+    }
+
+    /// Resolves the film into an image.
+    ///
+    /// Each pixel is the weighted mean of its samples plus its splats
+    /// divided by the average number of samples per pixel.
+    ///
+    /// # Returns
+    ///
+    /// The color of every pixel, indexed by `j * width + i`
+    pub fn image(&self) -> Vec<Color> {
+        let total_samples = self.total_samples();
+        let splat_scale = match total_samples {
+            0 => 0.0,
+            n => self.pixels.len() as f64 / n as f64,
+        };
+
+        self.pixels
+            .iter()
+            .zip(&self.splats)
+            .map(|(pixel, splat)| pixel.mean() + splat_scale * *splat)
+            .collect()
+    }
+
+    /// Writes the film to a byte stream.
+    ///
+    /// Every value is stored with its exact bits, so reading the film back
+    /// gives an identical film.
+    ///
+    /// # Arguments
+    ///
+    /// * `out` - The stream to write to
+    ///
+    /// # Returns
+    ///
+    /// An error if writing fails
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(&self.width.to_le_bytes())?;
+        out.write_all(&self.height.to_le_bytes())?;
+        self.filter.filter().write_to(out)?;
+        out.write_all(&self.filter.radius().to_le_bytes())?;
+        for (pixel, splat) in self.pixels.iter().zip(&self.splats) {
+            pixel.write_to(out)?;
+            write_vec3(out, splat)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a film written by `write_to` from a byte stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The stream to read from
+    ///
+    /// # Returns
+    ///
+    /// The film, or an error if reading fails or the stream ends before
+    /// every pixel of the image size in its header
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<Self> {
+        let width = read_u32(input)?;
+        let height = read_u32(input)?;
+        let filter = Filter::read_from(input)?;
+        let radius = read_f64(input)?;
+
+        let count = (width as usize)
+            .checked_mul(height as usize)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("film size {}x{} is too large", width, height),
+                )
+            })?;
+        let mut pixels = Vec::with_capacity(count.min(MAX_PREALLOCATED_PIXELS));
+        let mut splats = Vec::with_capacity(count.min(MAX_PREALLOCATED_PIXELS));
+        for _ in 0..count {
+            pixels.push(FilmPixel::read_from(input)?);
+            splats.push(read_vec3(input)?);
+        }
+
+        Ok(Self {
+            width,
+            height,
+            filter: FilterTable::new(filter, radius),
+            pixels,
+            splats,
+        })
+    }
+
+    /// Returns the index of a pixel in the pixel buffers.
+    fn index(&self, i: u32, j: u32) -> usize {
+        j as usize * self.width as usize + i as usize
+    }
+}
+
+/// Writes the exact bits of a vector to a byte stream.
+pub(crate) fn write_vec3<W: Write>(out: &mut W, v: &Vec3) -> io::Result<()> {
+    for k in 0..3 {
+        out.write_all(&v[k].to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Reads a vector written by `write_vec3` from a byte stream.
+pub(crate) fn read_vec3<R: Read>(input: &mut R) -> io::Result<Vec3> {
+    Ok(Vec3::new(
+        read_f64(input)?,
+        read_f64(input)?,
+        read_f64(input)?,
+    ))
+}
+
+/// Reads a little-endian `u32` from a byte stream.
+pub(crate) fn read_u32<R: Read>(input: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// Reads a little-endian `u64` from a byte stream.
+pub(crate) fn read_u64<R: Read>(input: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Reads a little-endian `f64` from a byte stream.
+pub(crate) fn read_f64<R: Read>(input: &mut R) -> io::Result<f64> {
+    Ok(f64::from_bits(read_u64(input)?))
+}
@@ -0,0 +1,294 @@
+//! Pixel reconstruction filters for the raytracer.
+//!
+//! A filter decides how much a sample contributes to the pixels around it.
+//! This module provides:
+//! - `Filter`: The shape of a filter (box, tent, Gaussian, Mitchell–Netravali
+//!   or Lanczos)
+//! - `FilterTable`: A filter of a given radius, normalized and tabulated so
+//!   that positions can be sampled in proportion to it
+//!
+//! Camera samples are not spread over the pixels around them. Instead,
+//! each pixel places its samples according to the filter and weights them
+//! by the filter over the sampling density, which is 1 wherever the filter
+//! is positive. Pixels therefore stay independent of each other, while the
+//! negative lobes of filters such as Mitchell–Netravali and Lanczos still
+//! sharpen the image. Light-tracing splats, which land anywhere on the
+//! image, are spread over the pixels within the filter's radius.
+//!
+//! All filters are separable: the 2D filter is the product of the 1D
+//! filter along each axis.
+
+use std::{
+    f64::consts::PI,
+    io::{self, Read, Write},
+};
+
+use crate::film::read_f64;
+
+/// The number of bins the filter is tabulated in along each axis.
+const TABLE_SIZE: usize = 256;
+
+/// The shape of a pixel reconstruction filter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    /// Equal weight everywhere within the radius; with a radius of 0.5 each
+    /// sample counts only for its own pixel
+    Box,
+    /// Weight falling linearly to zero at the radius, typically 1.0
+    Tent,
+    /// A Gaussian with standard deviation `sigma`, shifted to reach zero at
+    /// the radius, typically 1.5 with `sigma` 0.5
+    Gaussian {
+        /// The standard deviation in pixels
+        sigma: f64,
+    },
+    /// The Mitchell–Netravali cubic, typically with a radius of 2.0 and
+    /// `b` = `c` = 1/3
+    Mitchell {
+        /// The blur parameter
+        b: f64,
+        /// The ringing parameter
+        c: f64,
+    },
+    /// A sinc windowed by a wider sinc, typically with a radius of 3.0 and
+    /// `tau` 3.0
+    Lanczos {
+        /// The number of sinc lobes covered by the window
+        tau: f64,
+    },
+}
+
+impl Default for Filter {
+    /// Returns the box filter.
+    fn default() -> Self {
+        Filter::Box
+    }
+}
+
+impl Filter {
+    /// Evaluates the filter along one axis.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The offset from the filter's center, in pixels
+    /// * `radius` - The radius of the filter, in pixels
+    ///
+    /// # Returns
+    ///
+    /// The unnormalized filter value, which is 0.0 beyond the radius
+    pub fn evaluate_1d(&self, x: f64, radius: f64) -> f64 {
+        let x = x.abs();
+        if x > radius {
+            return 0.0;
+        }
+
+        match *self {
+            Filter::Box => 1.0,
+            Filter::Tent => radius - x,
+            Filter::Gaussian { sigma } => {
+                let gaussian = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(radius)).max(0.0)
+            }
+            Filter::Mitchell { b, c } => {
+                let x = 2.0 * x / radius;
+                match x <= 1.0 {
+                    true => {
+                        ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                            + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                            + (6.0 - 2.0 * b))
+                            / 6.0
+                    }
+                    false => {
+                        ((-b - 6.0 * c) * x * x * x
+                            + (6.0 * b + 30.0 * c) * x * x
+                            + (-12.0 * b - 48.0 * c) * x
+                            + (8.0 * b + 24.0 * c))
+                            / 6.0
+                    }
+                }
+            }
+            Filter::Lanczos { tau } => sinc(x) * sinc(x / tau),
+        }
+    }
+
+    /// Writes the filter to a byte stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `out` - The stream to write to
+    ///
+    /// # Returns
+    ///
+    /// An error if writing fails
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let (kind, p0, p1) = match *self {
+            Filter::Box => (0, 0.0, 0.0),
+            Filter::Tent => (1, 0.0, 0.0),
+            Filter::Gaussian { sigma } => (2, sigma, 0.0),
+            Filter::Mitchell { b, c } => (3, b, c),
+            Filter::Lanczos { tau } => (4, tau, 0.0),
+        };
+        out.write_all(&[kind])?;
+        out.write_all(&f64::to_le_bytes(p0))?;
+        out.write_all(&f64::to_le_bytes(p1))
+    }
+
+    /// Reads a filter written by `write_to` from a byte stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The stream to read from
+    ///
+    /// # Returns
+    ///
+    /// The filter, or an error if reading fails or the data is invalid
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<Self> {
+        let mut kind = [0];
+        input.read_exact(&mut kind)?;
+        let p0 = read_f64(input)?;
+        let p1 = read_f64(input)?;
+        match kind[0] {
+            0 => Ok(Filter::Box),
+            1 => Ok(Filter::Tent),
+            2 => Ok(Filter::Gaussian { sigma: p0 }),
+            3 => Ok(Filter::Mitchell { b: p0, c: p1 }),
+            4 => Ok(Filter::Lanczos { tau: p0 }),
+            kind => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown filter kind {}", kind),
+            )),
+        }
+    }
+}
+
+/// A filter of a given radius, normalized and tabulated for sampling.
+///
+/// Offsets are sampled along each axis in proportion to the absolute value
+/// of the filter, tabulated in bins over the filter's width.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterTable {
+    /// The shape of the filter
+    filter: Filter,
+    /// The radius of the filter in pixels
+    radius: f64,
+    /// The cumulative absolute filter value at the end of each bin,
+    /// normalized to end at 1
+    cdf: Vec<f64>,
+    /// The integral of the filter along one axis
+    integral: f64,
+}
+
+impl FilterTable {
+    /// Tabulates a filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The shape of the filter
+    /// * `radius` - The radius of the filter in pixels, at least a small positive value
+    pub fn new(filter: Filter, radius: f64) -> Self {
+        let radius = radius.max(1e-3);
+        let bin_width = 2.0 * radius / TABLE_SIZE as f64;
+        let values: Vec<f64> = (0..TABLE_SIZE)
+            .map(|bin| filter.evaluate_1d(-radius + (bin as f64 + 0.5) * bin_width, radius))
+            .collect();
+
+        let mut cdf = Vec::with_capacity(TABLE_SIZE);
+        let mut total = 0.0;
+        for value in &values {
+            total += value.abs();
+            cdf.push(total);
+        }
+        for c in &mut cdf {
+            *c /= total;
+        }
+
+        Self {
+            filter,
+            radius,
+            cdf,
+            integral: values.iter().sum::<f64>() * bin_width,
+        }
+    }
+
+    /// Returns the shape of the filter
+    pub fn filter(&self) -> Filter {
+        self.filter
+    }
+
+    /// Returns the radius of the filter in pixels
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    /// Evaluates the filter, normalized to integrate to 1.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The horizontal offset from the filter's center, in pixels
+    /// * `y` - The vertical offset from the filter's center, in pixels
+    pub fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.filter.evaluate_1d(x, self.radius) * self.filter.evaluate_1d(y, self.radius)
+            / (self.integral * self.integral)
+    }
+
+    /// Samples an offset from the filter's center.
+    ///
+    /// # Arguments
+    ///
+    /// * `u` - A uniform sample in \[0,1) for the horizontal offset
+    /// * `v` - A uniform sample in \[0,1) for the vertical offset
+    ///
+    /// # Returns
+    ///
+    /// The horizontal and vertical offsets in pixels, and the weight of the
+    /// sample: the normalized filter value over the sampling density, which
+    /// is 1 wherever the filter is positive and tabulated exactly
+    pub fn sample(&self, u: f64, v: f64) -> (f64, f64, f64) {
+        let (x, weight_x) = self.sample_1d(u);
+        let (y, weight_y) = self.sample_1d(v);
+        (x, y, weight_x * weight_y)
+    }
+
+    /// Samples an offset along one axis.
+    ///
+    /// # Returns
+    ///
+    /// The offset in pixels and its weight
+    fn sample_1d(&self, u: f64) -> (f64, f64) {
+        let bin = self.cdf.partition_point(|&c| c <= u).min(TABLE_SIZE - 1);
+        let start = match bin {
+            0 => 0.0,
+            _ => self.cdf[bin - 1],
+        };
+        let width = self.cdf[bin] - start;
+        let t = match width > 0.0 {
+            true => (u - start) / width,
+            false => 0.5,
+        };
+
+        let bin_width = 2.0 * self.radius / TABLE_SIZE as f64;
+        let x = -self.radius + (bin as f64 + t) * bin_width;
+        let pdf = width / bin_width;
+        let value = self.filter.evaluate_1d(x, self.radius);
+        match pdf > 0.0 {
+            true => (x, value / (pdf * self.integral)),
+            false => (x, 0.0),
+        }
+    }
+}
+
+impl Default for FilterTable {
+    /// Returns a box filter with a radius of half a pixel, which counts
+    /// every sample for its own pixel only.
+    fn default() -> Self {
+        Self::new(Filter::Box, 0.5)
+    }
+}
+
+/// Returns sin(πx)/(πx), which is 1 at 0.
+fn sinc(x: f64) -> f64 {
+    match x.abs() < 1e-5 {
+        true => 1.0,
+        false => (PI * x).sin() / (PI * x),
+    }
+}
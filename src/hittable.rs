@@ -11,6 +11,7 @@ use crate::{
     interval::Interval,
     material::Material,
     ray::Ray,
+    sampler::Sampler,
     vec3::{Point3, Vec3},
 };
 
@@ -22,9 +23,11 @@ use crate::{
 /// - The surface normal at the intersection
 /// - The material of the intersected object
 /// - The distance along the ray to the intersection
+/// - The surface coordinates of the intersection
 /// - Whether the ray hit the front or back face of the object
+/// - Whether the point lies inside a participating medium
 #[allow(dead_code)]
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct HitRecord {
     /// The point where the ray intersects the object
     pub p: Point3,
@@ -34,8 +37,15 @@ pub struct HitRecord {
     pub mat: Option<Arc<dyn Material>>,
     /// The distance along the ray to the intersection point
     pub t: f64,
+    /// The horizontal surface coordinate of the intersection point
+    pub u: f64,
+    /// The vertical surface coordinate of the intersection point
+    pub v: f64,
     /// Whether the ray hit the front face of the object
     pub front_face: bool,
+    /// Whether the point is a scattering event inside a participating
+    /// medium rather than on a surface; the normal is then meaningless
+    pub in_medium: bool,
 }
 
 impl HitRecord {
@@ -47,14 +57,27 @@ impl HitRecord {
     /// * `normal` - The surface normal at the intersection
     /// * `mat` - The material of the intersected object
     /// * `t` - The distance along the ray to the intersection
+    /// * `u` - The horizontal surface coordinate of the intersection
+    /// * `v` - The vertical surface coordinate of the intersection
     /// * `front_face` - Whether the ray hit the front face
-    pub fn new(p: Point3, normal: Vec3, mat: Arc<dyn Material>, t: f64, front_face: bool) -> Self {
+    pub fn new(
+        p: Point3,
+        normal: Vec3,
+        mat: Arc<dyn Material>,
+        t: f64,
+        u: f64,
+        v: f64,
+        front_face: bool,
+    ) -> Self {
         Self {
             p,
             normal,
             mat: Some(mat),
             t,
+            u,
+            v,
             front_face,
+            in_medium: false,
         }
     }
 
@@ -80,6 +103,18 @@ impl HitRecord {
     }
 }
 
+/// A point sampled on the surface of a hittable object.
+///
+/// Integrators that start paths on light sources, such as bidirectional
+/// path tracing, use surface samples to pick points on emitters.
+pub struct SurfaceSample {
+    /// The sampled point, with the outward-facing normal, its material and
+    /// surface coordinates; `front_face` is always true
+    pub rec: HitRecord,
+    /// The probability density of the point with respect to surface area
+    pub pdf: f64,
+}
+
 /// A trait for objects that can be intersected by rays.
 ///
 /// This trait must be implemented by any object that can be intersected
@@ -92,10 +127,78 @@ pub trait Hittable: Send + Sync {
     ///
     /// * `r` - The ray to test for intersection
     /// * `ray_t` - The interval along the ray to check for intersection
+    /// * `sampler` - The source of random numbers, used by participating
+    ///   media to sample scattering events
     ///
     /// # Returns
     ///
     /// If there is an intersection, returns a `HitRecord` containing the
     /// intersection details. Otherwise returns `None`.
-    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord>;
+    fn hit(&self, r: &Ray, ray_t: Interval, sampler: &mut dyn Sampler) -> Option<HitRecord>;
+
+    /// Estimates the fraction of light that passes along a ray segment.
+    ///
+    /// Surfaces are treated as opaque, so the default returns 0.0 if the
+    /// segment hits the object and 1.0 otherwise. Participating media
+    /// override this to return a (possibly stochastic) estimate of their
+    /// transmittance, which is lower variance than testing for a scattering
+    /// event with `hit`.
+    ///
+    /// # Arguments
+    ///
+    /// * `r` - The ray along which light travels
+    /// * `ray_t` - The segment of the ray to consider
+    /// * `sampler` - The source of random numbers
+    ///
+    /// # Returns
+    ///
+    /// The transmittance of the segment, between 0.0 and 1.0
+    fn transmittance(&self, r: &Ray, ray_t: Interval, sampler: &mut dyn Sampler) -> f64 {
+        match self.hit(r, ray_t, sampler) {
+            Some(_) => 0.0,
+            None => 1.0,
+        }
+    }
+
+    /// Samples a point on the surface of the object.
+    ///
+    /// Objects that can be used as area lights override this to sample
+    /// points with a known density. The default returns `None`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sampler` - The source of random numbers
+    ///
+    /// # Returns
+    ///
+    /// A point on the surface and its area density, or `None` if the object
+    /// does not support surface sampling
+    fn sample_surface(&self, _sampler: &mut dyn Sampler) -> Option<SurfaceSample> {
+        None
+    }
+
+    /// Returns the area density with which `sample_surface` samples a point.
+    ///
+    /// # Arguments
+    ///
+    /// * `p` - A point on the surface of the object
+    ///
+    /// # Returns
+    ///
+    /// The density with respect to surface area, or 0.0 if `p` is not on the
+    /// surface or the object does not support surface sampling
+    fn surface_pdf(&self, _p: &Point3) -> f64 {
+        0.0
+    }
+
+    /// Appends the materials of the object to a list.
+    ///
+    /// The materials are visited in a fixed order for a given scene, so
+    /// their positions in the list identify them across renders. The default
+    /// adds nothing.
+    ///
+    /// # Arguments
+    ///
+    /// * `materials` - The list to append the materials to
+    fn collect_materials(&self, _materials: &mut Vec<Arc<dyn Material>>) {}
 }
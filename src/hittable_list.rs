@@ -5,9 +5,14 @@
 //! allowing it to be used as a container for other hittable objects while
 //! maintaining the same interface.
 
+use std::sync::Arc;
+
 use crate::{
-    hittable::{HitRecord, Hittable},
+    hittable::{HitRecord, Hittable, SurfaceSample},
     interval::Interval,
+    material::Material,
+    sampler::Sampler,
+    vec3::Point3,
 };
 
 /// A collection of hittable objects in the scene.
@@ -63,17 +68,23 @@ impl Hittable for HittableList {
     ///
     /// * `r` - The ray to test for intersection
     /// * `ray_t` - The interval along the ray to check for intersection
+    /// * `sampler` - The source of random numbers, passed on to the objects
     ///
     /// # Returns
     ///
     /// If there is an intersection with any object, returns a `HitRecord`
     /// containing the details of the closest intersection. Otherwise returns
     /// `None`.
-    fn hit(&self, r: &crate::ray::Ray, ray_t: Interval) -> Option<HitRecord> {
+    fn hit(
+        &self,
+        r: &crate::ray::Ray,
+        ray_t: Interval,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
         let mut closest_so_far = ray_t.max;
         let mut hit_record = None;
         for object in &self.objects {
-            if let Some(rec) = object.hit(r, Interval::new(ray_t.min, closest_so_far)) {
+            if let Some(rec) = object.hit(r, Interval::new(ray_t.min, closest_so_far), sampler) {
                 closest_so_far = rec.t;
                 hit_record = Some(rec);
             }
@@ -81,4 +92,62 @@ impl Hittable for HittableList {
 
         hit_record
     }
+
+    /// Estimates the transmittance along a ray segment through every object.
+    ///
+    /// The transmittance of the list is the product of the transmittances
+    /// of its objects, stopping early once the segment is fully blocked.
+    fn transmittance(
+        &self,
+        r: &crate::ray::Ray,
+        ray_t: Interval,
+        sampler: &mut dyn Sampler,
+    ) -> f64 {
+        let mut transmittance = 1.0;
+        for object in &self.objects {
+            transmittance *= object.transmittance(r, ray_t, sampler);
+            if transmittance == 0.0 {
+                break;
+            }
+        }
+
+        transmittance
+    }
+
+    /// Samples a point on a randomly chosen object of the list.
+    ///
+    /// Each object is chosen with equal probability, so the density of the
+    /// sample is the object's own density divided by the number of objects.
+    fn sample_surface(&self, sampler: &mut dyn Sampler) -> Option<SurfaceSample> {
+        if self.objects.is_empty() {
+            return None;
+        }
+
+        let n = self.objects.len();
+        let index = ((sampler.get_1d() * n as f64) as usize).min(n - 1);
+        let mut sample = self.objects[index].sample_surface(sampler)?;
+        sample.pdf /= n as f64;
+        Some(sample)
+    }
+
+    /// Returns the density of sampling a point, averaged over the objects.
+    fn surface_pdf(&self, p: &Point3) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+
+        let sum: f64 = self
+            .objects
+            .iter()
+            .map(|object| object.surface_pdf(p))
+            .sum();
+        sum / self.objects.len() as f64
+    }
+
+    /// Appends the materials of every object, in the order they were added.
+    fn collect_materials(&self, materials: &mut Vec<Arc<dyn Material>>) {
+        for object in &self.objects {
+            object.collect_materials(materials);
+        }
+    }
 }
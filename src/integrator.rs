@@ -0,0 +1,418 @@
+//! Light transport integrators for the raytracer.
+//!
+//! This module provides the `Integrator` trait, which computes the color
+//! seen along a camera ray, and its implementations:
+//! - `PathTracer`: Recursive Monte Carlo path tracing (the default)
+//! - `IterativePathTracer`: Loop-based path tracing with Russian roulette
+//!
+//! The camera delegates all light transport to its integrator, so new
+//! algorithms can be added without changing the camera.
+
+use crate::{
+    camera::Camera,
+    hittable::Hittable,
+    interval::Interval,
+    material::Scatter,
+    ray::Ray,
+    sampler::Sampler,
+    spectrum::{SampledSpectrum, SampledWavelengths},
+    vec3::Color,
+};
+
+/// A trait for algorithms that compute the light arriving along a ray.
+pub trait Integrator: Send + Sync {
+    /// Computes the color of a ray through the scene.
+    ///
+    /// # Arguments
+    ///
+    /// * `r` - The camera ray to trace
+    /// * `depth` - The maximum number of ray bounces
+    /// * `world` - The scene to trace through
+    /// * `sampler` - The source of random numbers for the camera sample
+    ///
+    /// # Returns
+    ///
+    /// The color contribution of the ray
+    fn ray_color(
+        &self,
+        r: &Ray,
+        depth: u32,
+        world: &dyn Hittable,
+        sampler: &mut dyn Sampler,
+    ) -> Color;
+
+    /// Prepares the integrator for rendering a scene.
+    ///
+    /// The camera calls this once before tracing any camera rays. Integrators
+    /// that precompute data from the scene, such as photon maps, override it;
+    /// the default does nothing.
+    ///
+    /// # Arguments
+    ///
+    /// * `world` - The scene about to be rendered
+    /// * `seed` - The seed of the render, which any random numbers drawn
+    ///   here should be derived from
+    fn preprocess(&mut self, _world: &dyn Hittable, _seed: u64) {}
+
+    /// Describes the integrator and the settings that change its image.
+    ///
+    /// Checkpoints record the description, so a render is only resumed with
+    /// the integrator it was started with.
+    fn description(&self) -> String;
+
+    /// Computes the spectral radiance of a ray through the scene.
+    ///
+    /// The default implementation traces the ray in RGB and upsamples the
+    /// result, which is enough for integrators that do not model
+    /// wavelength-dependent effects.
+    ///
+    /// # Arguments
+    ///
+    /// * `r` - The camera ray to trace, carrying the hero wavelength
+    /// * `lambda` - The wavelengths traced along the path
+    /// * `depth` - The maximum number of ray bounces
+    /// * `world` - The scene to trace through
+    /// * `sampler` - The source of random numbers for the camera sample
+    ///
+    /// # Returns
+    ///
+    /// The radiance of the ray at each sampled wavelength
+    fn ray_color_spectral(
+        &self,
+        r: &Ray,
+        lambda: &mut SampledWavelengths,
+        depth: u32,
+        world: &dyn Hittable,
+        sampler: &mut dyn Sampler,
+    ) -> SampledSpectrum {
+        lambda.upsample(&self.ray_color(r, depth, world, sampler))
+    }
+
+    /// Computes the color of a camera ray, possibly contributing to other pixels.
+    ///
+    /// Integrators that trace paths from the lights, such as bidirectional
+    /// path tracing, can connect them to the camera and record the result
+    /// as splats on whichever pixel they land. The default traces the ray
+    /// with `ray_color` and records no splats.
+    ///
+    /// # Arguments
+    ///
+    /// * `r` - The camera ray to trace
+    /// * `depth` - The maximum number of ray bounces
+    /// * `world` - The scene to trace through
+    /// * `camera` - The camera generating the ray
+    /// * `splats` - Receives contributions to arbitrary pixels
+    /// * `sampler` - The source of random numbers for the camera sample
+    ///
+    /// # Returns
+    ///
+    /// The color contribution of the ray to its own pixel
+    fn ray_color_with_splats(
+        &self,
+        r: &Ray,
+        depth: u32,
+        world: &dyn Hittable,
+        _camera: &Camera,
+        _splats: &mut Vec<Splat>,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        self.ray_color(r, depth, world, sampler)
+    }
+
+    /// Renders the whole image at once.
+    ///
+    /// Integrators that do not work pixel by pixel, such as Metropolis light
+    /// transport, override this to take over the camera's render loop. The
+    /// default returns `None`, and the camera samples each pixel with
+    /// `ray_color_with_splats` (or `ray_color_spectral`).
+    ///
+    /// # Arguments
+    ///
+    /// * `camera` - The initialized camera to render with
+    /// * `world` - The scene to render
+    ///
+    /// # Returns
+    ///
+    /// The final linear color of every pixel, indexed by `j * image_width + i`
+    /// in the pixel coordinates of `Camera::get_ray`, or `None` to let the
+    /// camera render the image
+    fn render_image(&self, _camera: &Camera, _world: &dyn Hittable) -> Option<Vec<Color>> {
+        None
+    }
+}
+
+/// A color contribution to a point on the image other than the pixel being sampled.
+///
+/// Splats are spread over the pixels around them by the camera's
+/// reconstruction filter and added to the image, divided by the average
+/// number of samples per pixel.
+#[derive(Debug, Clone, Copy)]
+pub struct Splat {
+    /// The horizontal film coordinate, where pixel `i` of `Camera::get_ray`
+    /// covers \[i,i+1)
+    pub x: f64,
+    /// The vertical film coordinate, where pixel `j` of `Camera::get_ray`
+    /// covers \[j,j+1)
+    pub y: f64,
+    /// The color contribution
+    pub color: Color,
+}
+
+/// Returns the sky color seen along a ray that escapes the scene.
+///
+/// # Arguments
+///
+/// * `r` - The escaping ray
+///
+/// # Returns
+///
+/// A vertical gradient from white at the horizon to light blue overhead
+pub fn background(r: &Ray) -> Color {
+    let unit_direction = r.direction().unit_vector();
+    let a = (unit_direction.y() + 1.0) * 0.5;
+    (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
+}
+
+/// A recursive unidirectional path tracer.
+///
+/// At every hit the material's emission is added and the material scatters
+/// the ray once; the path continues until it escapes to the sky, is
+/// absorbed, or reaches the maximum depth.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PathTracer;
+
+impl Integrator for PathTracer {
+    /// Computes the color of a ray through the scene.
+    ///
+    /// This method recursively traces a ray through the scene, handling
+    /// reflection, refraction, and background color. It implements the
+    /// Monte Carlo path tracing algorithm.
+    fn ray_color(
+        &self,
+        r: &Ray,
+        depth: u32,
+        world: &dyn Hittable,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        if depth == 0 {
+            return Color::default();
+        }
+        match world.hit(r, Interval::new(0.001, f64::INFINITY), sampler) {
+            Some(rec) => {
+                let mut scattered = Ray::default();
+                let mut attenuation = Color::default();
+                let mat = rec.mat.as_ref().unwrap();
+                let emitted = mat.emitted(r, &rec);
+
+                match mat.scatter(r, &rec, &mut attenuation, &mut scattered, sampler) {
+                    Scatter::Absorbed => emitted,
+                    Scatter::Scattered | Scatter::Specular => {
+                        emitted
+                            + attenuation * self.ray_color(&scattered, depth - 1, world, sampler)
+                    }
+                }
+            }
+            None => background(r),
+        }
+    }
+
+    /// Computes the spectral radiance of a ray through the scene.
+    ///
+    /// Material and sky colors are upsampled to spectra at the sampled
+    /// wavelengths. When the path meets a dispersive material, only the hero
+    /// wavelength (the one carried by the ray) remains valid, so the others
+    /// are terminated.
+    fn ray_color_spectral(
+        &self,
+        r: &Ray,
+        lambda: &mut SampledWavelengths,
+        depth: u32,
+        world: &dyn Hittable,
+        sampler: &mut dyn Sampler,
+    ) -> SampledSpectrum {
+        if depth == 0 {
+            return SampledSpectrum::default();
+        }
+        match world.hit(r, Interval::new(0.001, f64::INFINITY), sampler) {
+            Some(rec) => {
+                let mut scattered = Ray::default();
+                let mut attenuation = Color::default();
+                let mat = rec.mat.as_ref().unwrap();
+                let emitted = lambda.upsample(&mat.emitted(r, &rec));
+
+                if mat.scatter(r, &rec, &mut attenuation, &mut scattered, sampler)
+                    == Scatter::Absorbed
+                {
+                    return emitted;
+                }
+                if mat.is_dispersive() {
+                    lambda.terminate_secondary();
+                }
+
+                emitted
+                    + lambda.upsample(&attenuation)
+                        * self.ray_color_spectral(&scattered, lambda, depth - 1, world, sampler)
+            }
+            None => lambda.upsample(&background(r)),
+        }
+    }
+
+    /// Names the integrator, which has no settings.
+    fn description(&self) -> String {
+        "path tracer".to_string()
+    }
+}
+
+/// An iterative path tracer with Russian roulette path termination.
+///
+/// Instead of recursing, the path is followed in a loop while tracking its
+/// throughput (the product of all attenuations so far), so very deep paths
+/// cannot overflow the stack. After `min_depth` bounces, paths are randomly
+/// terminated with a probability that grows as their throughput drops, and
+/// surviving paths are reweighted to keep the estimate unbiased. This keeps
+/// dark scenes from always tracing to the maximum depth.
+#[derive(Debug, Clone, Copy)]
+pub struct IterativePathTracer {
+    /// Number of bounces before Russian roulette may terminate a path
+    pub min_depth: u32,
+}
+
+impl IterativePathTracer {
+    /// Creates a new iterative path tracer.
+    ///
+    /// # Arguments
+    ///
+    /// * `min_depth` - Number of bounces before Russian roulette may terminate a path
+    pub fn new(min_depth: u32) -> Self {
+        Self { min_depth }
+    }
+
+    /// Applies Russian roulette to a path after the given number of bounces.
+    ///
+    /// # Arguments
+    ///
+    /// * `bounces` - The number of bounces the path has made so far
+    /// * `max_throughput` - The largest component of the path throughput
+    /// * `sampler` - The source of random numbers
+    ///
+    /// # Returns
+    ///
+    /// The factor to scale the surviving path's throughput by, or `None` if
+    /// the path is terminated
+    fn roulette(
+        &self,
+        bounces: u32,
+        max_throughput: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Option<f64> {
+        if bounces < self.min_depth {
+            return Some(1.0);
+        }
+
+        let survival = max_throughput.min(1.0);
+        match sampler.get_1d() < survival {
+            true => Some(1.0 / survival),
+            false => None,
+        }
+    }
+}
+
+impl Default for IterativePathTracer {
+    /// Creates an iterative path tracer that starts Russian roulette after three bounces.
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
+
+impl Integrator for IterativePathTracer {
+    /// Computes the color of a ray by following its path in a loop.
+    fn ray_color(
+        &self,
+        r: &Ray,
+        depth: u32,
+        world: &dyn Hittable,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let mut radiance = Color::default();
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut ray = *r;
+
+        for bounces in 0..depth {
+            let Some(rec) = world.hit(&ray, Interval::new(0.001, f64::INFINITY), sampler) else {
+                return radiance + throughput * background(&ray);
+            };
+
+            let mut scattered = Ray::default();
+            let mut attenuation = Color::default();
+            let mat = rec.mat.as_ref().unwrap();
+            radiance += throughput * mat.emitted(&ray, &rec);
+            if mat.scatter(&ray, &rec, &mut attenuation, &mut scattered, sampler)
+                == Scatter::Absorbed
+            {
+                break;
+            }
+
+            throughput = throughput * attenuation;
+            let max_throughput = throughput.x().max(throughput.y()).max(throughput.z());
+            match self.roulette(bounces + 1, max_throughput, sampler) {
+                Some(scale) => throughput *= scale,
+                None => break,
+            }
+
+            ray = scattered;
+        }
+
+        radiance
+    }
+
+    /// Computes the spectral radiance of a ray by following its path in a loop.
+    ///
+    /// Dispersive materials terminate the secondary wavelengths, as in
+    /// `PathTracer`.
+    fn ray_color_spectral(
+        &self,
+        r: &Ray,
+        lambda: &mut SampledWavelengths,
+        depth: u32,
+        world: &dyn Hittable,
+        sampler: &mut dyn Sampler,
+    ) -> SampledSpectrum {
+        let mut radiance = SampledSpectrum::default();
+        let mut throughput = SampledSpectrum::splat(1.0);
+        let mut ray = *r;
+
+        for bounces in 0..depth {
+            let Some(rec) = world.hit(&ray, Interval::new(0.001, f64::INFINITY), sampler) else {
+                return radiance + throughput * lambda.upsample(&background(&ray));
+            };
+
+            let mut scattered = Ray::default();
+            let mut attenuation = Color::default();
+            let mat = rec.mat.as_ref().unwrap();
+            radiance += throughput * lambda.upsample(&mat.emitted(&ray, &rec));
+            if mat.scatter(&ray, &rec, &mut attenuation, &mut scattered, sampler)
+                == Scatter::Absorbed
+            {
+                break;
+            }
+            if mat.is_dispersive() {
+                lambda.terminate_secondary();
+            }
+
+            throughput = throughput * lambda.upsample(&attenuation);
+            match self.roulette(bounces + 1, throughput.max_value(), sampler) {
+                Some(scale) => throughput = throughput * scale,
+                None => break,
+            }
+
+            ray = scattered;
+        }
+
+        radiance
+    }
+
+    /// Names the integrator and its Russian roulette depth.
+    fn description(&self) -> String {
+        format!("iterative path tracer, min depth {}", self.min_depth)
+    }
+}
@@ -1,35 +1,77 @@
 //! A physically-based raytracer implemented in Rust.
 //!
 //! This crate implements a Monte Carlo raytracer that supports:
-//! - Diffuse materials (Lambertian)
+//! - Diffuse materials (Lambertian and Oren-Nayar)
 //! - Metal materials with configurable fuzz
-//! - Dielectric materials (glass)
+//! - Dielectric materials (glass) with optional dispersion and absorption
+//! - Principled (Disney-style) layered materials
+//! - Random-walk subsurface scattering (skin, wax and marble)
+//! - Homogeneous and voxel-grid participating media (fog, smoke and clouds)
+//! - Textured material parameters
 //! - Camera with depth of field
-//! - Anti-aliasing
-//! - Gamma correction
+//! - RGB, single-wavelength and spectral (hero wavelength) rendering modes
+//! - Anti-aliasing with box, tent, Gaussian, Mitchell–Netravali and Lanczos
+//!   reconstruction filters
+//! - Adaptive sampling driven by per-pixel variance
+//! - Progressive rendering with snapshots and a time budget
+//! - Tile-based parallel rendering in scanline, spiral or Hilbert order
+//! - Checkpointing, resuming and merging of long renders
+//! - Distributed rendering over TCP with reissue of lost tiles
+//! - Independent, stratified, Halton and Sobol sampling
+//! - Seeded renders that are identical at any thread count
+//! - Pluggable light transport integrators
+//! - Area lights and bidirectional path tracing
+//! - Progressive photon mapping for caustics
+//! - Primary sample space Metropolis light transport
+//! - Ambient occlusion and false-color debug views
+//! - Exposure (EV or ISO, shutter and aperture) and Reinhard, Hable, ACES and
+//!   AgX tone mapping
+//! - Color management with sRGB, Rec.709, Rec.2020 and Display P3 working and
+//!   output color spaces
 //!
 //! The raytracer follows physically-based rendering principles and uses Monte Carlo
 //! integration for accurate light transport simulation.
 
+pub mod bdpt;
 pub mod camera;
+pub mod checkpoint;
+pub mod colorspace;
+pub mod constant_medium;
+pub mod debug;
+pub mod distributed;
+pub mod film;
+pub mod filter;
+pub mod grid_medium;
 pub mod hittable;
 pub mod hittable_list;
+pub mod integrator;
 pub mod interval;
 pub mod material;
+pub mod mlt;
+pub mod onb;
+pub mod photon;
+pub mod principled;
 pub mod ray;
+pub mod sampler;
+pub mod scene;
+pub mod spectrum;
 pub mod sphere;
+pub mod subsurface;
+pub mod texture;
+pub mod tile;
+pub mod tonemap;
 pub mod vec3;
 
 use std::fmt::Write as FmtWrite;
 use std::io::Write;
 
+use colorspace::ColorSpace;
 use interval::Interval;
-use rand::Rng;
 
-/// Converts a linear color component to gamma space (gamma 2).
+/// Converts a linear color component to gamma space with the sRGB transfer function.
 ///
-/// This function applies the square root transformation to convert from linear
-/// color space to gamma space, which is more perceptually uniform.
+/// This function applies the piecewise sRGB curve, a linear segment near
+/// black followed by a 2.4 power, which is more perceptually uniform.
 ///
 /// # Arguments
 ///
@@ -39,21 +81,20 @@ use rand::Rng;
 ///
 /// The color component in gamma space. Returns 0.0 for negative values.
 pub fn linear_to_gamma(linear_component: f64) -> f64 {
-    match linear_component > 0.0 {
-        true => linear_component.sqrt(),
-        false => 0.0,
-    }
+    ColorSpace::Srgb.encode(linear_component)
 }
 
 /// Writes a color to an output stream in PPM format.
 ///
-/// This function converts a color from linear space to gamma space and writes
-/// it as RGB values in the range [0, 255] to the specified output stream.
+/// This function encodes a linear color with the transfer function of the
+/// output color space and writes it as RGB values in the range [0, 255] to
+/// the specified output stream.
 ///
 /// # Arguments
 ///
 /// * `out` - The output stream to write to
-/// * `pixel_color` - The color to write
+/// * `pixel_color` - The linear color to write, in the output color space
+/// * `space` - The output color space, whose transfer function encodes the color
 ///
 /// # Returns
 ///
@@ -61,6 +102,7 @@ pub fn linear_to_gamma(linear_component: f64) -> f64 {
 pub fn write_color<T: Write>(
     out: &mut T,
     pixel_color: &vec3::Color,
+    space: ColorSpace,
 ) -> Result<usize, std::io::Error> {
     let mut str = String::new();
 
@@ -68,10 +110,10 @@ pub fn write_color<T: Write>(
     let mut g = pixel_color.y();
     let mut b = pixel_color.z();
 
-    // Apply linear to gamma transform for gamma 2
-    r = linear_to_gamma(r);
-    g = linear_to_gamma(g);
-    b = linear_to_gamma(b);
+    // Apply the output transfer function
+    r = space.encode(r);
+    g = space.encode(g);
+    b = space.encode(b);
 
     // translate the [0,1] component values to the byte range [0, 255].
     let intensity = Interval::new(0.000, 0.999);
@@ -84,25 +126,35 @@ pub fn write_color<T: Write>(
     out.write(str.as_bytes())
 }
 
-/// Generates a random double-precision float in the range [0, 1).
-///
-/// # Returns
+/// Writes an image to an output stream in PPM format.
 ///
-/// A random float between 0.0 (inclusive) and 1.0 (exclusive)
-pub fn random_double() -> f64 {
-    rand::rng().random()
-}
-
-/// Generates a random double-precision float in the specified range.
+/// Row 0 of the image is its bottom row, so the rows are written from the
+/// last to the first.
 ///
 /// # Arguments
 ///
-/// * `min` - The minimum value (inclusive)
-/// * `max` - The maximum value (exclusive)
+/// * `out` - The output stream to write to
+/// * `width` - The width of the image in pixels
+/// * `height` - The height of the image in pixels
+/// * `image` - The linear color of every pixel in the output color space,
+///   indexed by `j * width + i`
+/// * `space` - The output color space, whose transfer function encodes the image
 ///
 /// # Returns
 ///
-/// A random float between min (inclusive) and max (exclusive)
-pub fn random_double_range(min: f64, max: f64) -> f64 {
-    min + (max - min) * random_double()
+/// An error if writing fails
+pub fn write_image<T: Write>(
+    out: &mut T,
+    width: u32,
+    height: u32,
+    image: &[vec3::Color],
+    space: ColorSpace,
+) -> Result<(), std::io::Error> {
+    writeln!(out, "P3\n {0} {1} \n255", width, height)?;
+    for row in image.chunks(width as usize).rev() {
+        for pixel_color in row {
+            write_color(out, pixel_color, space)?;
+        }
+    }
+    out.flush()
 }
@@ -1,83 +1,230 @@
-use std::sync::Arc;
+use std::{
+    env, io,
+    net::TcpListener,
+    path::PathBuf,
+    process::{self, Command},
+};
 
 use raytracing::{
     camera::Camera,
-    hittable_list::HittableList,
-    material::{Dielectric, Lambertian, Metal},
-    random_double, random_double_range,
-    sphere::Sphere,
+    checkpoint, distributed,
+    sampler::{IndependentSampler, Sampler},
+    scene::{MaterialDescription, SceneDescription},
     vec3::{Color, Point3, Vec3},
+    write_image,
 };
 
-#[allow(dead_code)]
+/// The samples per pixel taken between two checkpoints of a command line render.
+const CHECKPOINT_PASS_SAMPLES: u32 = 16;
+
+/// The command line usage of the renderer.
+const USAGE: &str = "Usage:
+    raytracer [--seed <seed>] [--checkpoint <file>] > image.ppm
+        Renders the scene, checkpointing to <file> every 16 samples per pixel
+        and resuming from it if it exists
+    raytracer merge <checkpoint>... > image.ppm
+        Merges the checkpoints of renders with different seeds into one image
+    raytracer coordinator <address> [--local-workers <count>] > image.ppm
+        Renders the scene with the workers that connect to <address>,
+        optionally starting <count> workers on this machine
+    raytracer worker <address>
+        Renders tiles for the coordinator at <address>";
+
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("merge") => merge(&args[1..]),
+        Some("coordinator") => coordinator(&args[1..]),
+        Some("worker") => worker(&args[1..]),
+        _ => render(&args),
+    }
+}
+
+/// Renders the scene to stdout.
+///
+/// # Arguments
+///
+/// * `args` - The command line options
+fn render(args: &[String]) {
+    let mut cam = camera();
+    let mut options = args.iter();
+    while let Some(option) = options.next() {
+        match (option.as_str(), options.next()) {
+            ("--seed", Some(seed)) => cam.seed = seed.parse().unwrap_or_else(|_| usage()),
+            ("--checkpoint", Some(path)) => {
+                cam.checkpoint_path = Some(PathBuf::from(path));
+                cam.samples_per_pass = CHECKPOINT_PASS_SAMPLES;
+            }
+            _ => usage(),
+        }
+    }
+
+    cam.render(&scene().build());
+}
+
+/// Merges checkpoints into one image on stdout.
+///
+/// # Arguments
+///
+/// * `args` - The checkpoint files
+fn merge(args: &[String]) {
+    if args.is_empty() {
+        usage();
+    }
+
+    let paths: Vec<PathBuf> = args.iter().map(PathBuf::from).collect();
+    let cam = camera();
+    let film = checkpoint::merge_files(&paths).unwrap_or_else(|e| {
+        eprintln!("Error merging checkpoints: {}", e);
+        process::exit(1);
+    });
+    eprintln!(
+        "Merged {} samples per pixel",
+        film.total_samples() as f64 / film.pixels().len() as f64
+    );
+
+    write_image(
+        &mut io::stdout(),
+        film.width(),
+        film.height(),
+        &cam.develop(&film.image()),
+        cam.output_space,
+    )
+    .expect("Error writing to output");
+}
+
+/// Renders the scene with worker processes, writing the image to stdout.
+///
+/// # Arguments
+///
+/// * `args` - The address to listen on and the command line options
+fn coordinator(args: &[String]) {
+    let Some((address, options)) = args.split_first() else {
+        usage();
+    };
+    let local_workers: u32 = match options {
+        [] => 0,
+        [option, count] if option == "--local-workers" => count.parse().unwrap_or_else(|_| usage()),
+        _ => usage(),
+    };
+
+    let listener = TcpListener::bind(address).unwrap_or_else(|e| {
+        eprintln!("Error listening on {}: {}", address, e);
+        process::exit(1);
+    });
+    let address = listener.local_addr().expect("Error reading listen address");
+    eprintln!("Listening for workers on {}", address);
+
+    let exe = env::current_exe().expect("Error locating the renderer executable");
+    let mut children: Vec<_> = (0..local_workers)
+        .map(|_| {
+            Command::new(&exe)
+                .args(["worker", &address.to_string()])
+                .spawn()
+                .expect("Error starting a local worker")
+        })
+        .collect();
+
+    let cam = camera();
+    let film = distributed::coordinate(&listener, &cam, &scene()).unwrap_or_else(|e| {
+        eprintln!("Error coordinating workers: {}", e);
+        process::exit(1);
+    });
+    for child in &mut children {
+        let _ = child.wait();
+    }
+
+    write_image(
+        &mut io::stdout(),
+        film.width(),
+        film.height(),
+        &cam.resolve(&film),
+        cam.output_space,
+    )
+    .expect("Error writing to output");
+    eprintln!("Done.");
+}
+
+/// Renders tiles for a coordinator until its image is done.
+///
+/// # Arguments
+///
+/// * `args` - The address of the coordinator
+fn worker(args: &[String]) {
+    let [address] = args else {
+        usage();
+    };
+
+    if let Err(e) = distributed::work(address.as_str(), camera()) {
+        eprintln!("Worker error: {}", e);
+        process::exit(1);
+    }
+}
+
+/// Prints the command line usage and exits.
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+/// Describes the scene: a field of random small spheres around three large ones.
+fn scene() -> SceneDescription {
     // World
-    let mut world = HittableList::new();
+    let mut world = SceneDescription::new();
 
     // Ground
-    let ground_material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
-    world.add(Box::new(Sphere::new(
-        Point3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        ground_material,
-    )));
+    let ground_material = MaterialDescription::Lambertian(Color::new(0.5, 0.5, 0.5));
+    world.add_sphere(Point3::new(0.0, -1000.0, 0.0), 1000.0, ground_material);
 
     // Random spheres
+    let mut rng = IndependentSampler::default();
     for a in -11..11 {
         for b in -11..11 {
-            let choose_mat = random_double();
+            let choose_mat = rng.get_1d();
             let center = Point3::new(
-                a as f64 + 0.9 * random_double(),
+                a as f64 + 0.9 * rng.get_1d(),
                 0.2,
-                b as f64 + 0.9 * random_double(),
+                b as f64 + 0.9 * rng.get_1d(),
             );
 
             if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
                 if choose_mat < 0.8 {
                     // diffuse
-                    let albedo = Color::random_vec() * Color::random_vec();
-                    let sphere_material = Arc::new(Lambertian::new(albedo));
-                    world.add(Box::new(Sphere::new(center, 0.2, sphere_material)));
+                    let albedo = Color::random_vec(&mut rng) * Color::random_vec(&mut rng);
+                    let sphere_material = MaterialDescription::Lambertian(albedo);
+                    world.add_sphere(center, 0.2, sphere_material);
                 } else if choose_mat < 0.95 {
                     // metal
-                    let albedo = Color::random_vec_range(0.5, 1.0);
-                    let fuzz = random_double_range(0.0, 0.5);
-                    let sphere_material = Arc::new(Metal::new(albedo, fuzz));
-                    world.add(Box::new(Sphere::new(center, 0.2, sphere_material)));
+                    let albedo = Color::random_vec_range(0.5, 1.0, &mut rng);
+                    let fuzz = 0.5 * rng.get_1d();
+                    let sphere_material = MaterialDescription::Metal(albedo, fuzz);
+                    world.add_sphere(center, 0.2, sphere_material);
                 } else {
                     // glass
-                    let sphere_material = Arc::new(Dielectric::new(1.5));
-                    world.add(Box::new(Sphere::new(center, 0.2, sphere_material)));
+                    let sphere_material = MaterialDescription::Dielectric(1.5);
+                    world.add_sphere(center, 0.2, sphere_material);
                 }
             }
         }
     }
 
     // Dielectric sphere
-    let material1 = Arc::new(Dielectric::new(1.5));
-    world.add(Box::new(Sphere::new(
-        Point3::new(0.0, 1.0, 0.0),
-        1.0,
-        material1,
-    )));
+    let material1 = MaterialDescription::Dielectric(1.5);
+    world.add_sphere(Point3::new(0.0, 1.0, 0.0), 1.0, material1);
 
     // Lambertian sphere
-    let material2 = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
-    world.add(Box::new(Sphere::new(
-        Point3::new(-4.0, 1.0, 0.0),
-        1.0,
-        material2,
-    )));
+    let material2 = MaterialDescription::Lambertian(Color::new(0.4, 0.2, 0.1));
+    world.add_sphere(Point3::new(-4.0, 1.0, 0.0), 1.0, material2);
 
     // Metal sphere
-    let material3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
-    world.add(Box::new(Sphere::new(
-        Point3::new(4.0, 1.0, 0.0),
-        1.0,
-        material3,
-    )));
-
-    // Camera
+    let material3 = MaterialDescription::Metal(Color::new(0.7, 0.6, 0.5), 0.0);
+    world.add_sphere(Point3::new(4.0, 1.0, 0.0), 1.0, material3);
+
+    world
+}
+
+/// Sets up the camera looking at the scene.
+fn camera() -> Camera {
     let mut cam = Camera::default();
     cam.aspect_ratio = 16.0 / 9.0;
     cam.image_width = 1024;
@@ -92,5 +239,5 @@ fn main() {
     cam.defocus_angle = 0.1;
     cam.focus_dist = 10.0;
 
-    cam.render(&world);
+    cam
 }
@@ -3,16 +3,39 @@
 //! This module provides the `Material` trait and its implementations for
 //! different types of materials:
 //! - `Lambertian`: Diffuse materials that scatter light uniformly
+//! - `OrenNayar`: Rough diffuse materials such as clay, concrete and cloth
 //! - `Metal`: Reflective materials with optional fuzziness
 //! - `Dielectric`: Transparent materials that refract light
+//! - `Isotropic`: Phase function scattering uniformly inside volumes
+//! - `HenyeyGreenstein`: Anisotropic phase function for volumes
+//! - `DiffuseLight`: Emissive material for area lights
+//!
+//! Dielectrics take a `RefractiveIndex`, which may vary with wavelength to
+//! produce chromatic dispersion.
+
+use std::{f64::consts::PI, sync::Arc};
 
 use crate::{
     hittable::HitRecord,
-    random_double,
+    onb::Onb,
     ray::Ray,
+    sampler::Sampler,
+    texture::{SolidColor, Texture},
     vec3::{Color, Vec3},
 };
 
+/// The outcome of scattering a ray off a material.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scatter {
+    /// The ray was absorbed
+    Absorbed,
+    /// The direction was sampled with the density given by `scattering_pdf`
+    Scattered,
+    /// The direction was sampled from a delta lobe, such as a perfect mirror
+    /// or refraction, which `eval` and `scattering_pdf` cannot reproduce
+    Specular,
+}
+
 /// A trait for materials that can scatter light.
 ///
 /// This trait defines how materials interact with light rays in the scene.
@@ -27,17 +50,81 @@ pub trait Material: Send + Sync {
     /// * `rec` - The hit record containing information about the intersection
     /// * `attenuation` - The color attenuation of the scattered ray
     /// * `scattered` - The scattered ray
+    /// * `sampler` - The source of random numbers for choosing the scattered ray
     ///
     /// # Returns
     ///
-    /// True if the ray was scattered, false if it was absorbed
+    /// Whether the ray was absorbed, and otherwise whether the scattered
+    /// direction was sampled from a lobe with a density or from a delta lobe
     fn scatter(
         &self,
         r_in: &Ray,
         rec: &HitRecord,
         attenuation: &mut Color,
         scattered: &mut Ray,
-    ) -> bool;
+        sampler: &mut dyn Sampler,
+    ) -> Scatter;
+
+    /// Evaluates the BSDF multiplied by the cosine term for a pair of directions.
+    ///
+    /// Materials that only scatter into discrete directions (perfect mirrors
+    /// and glass) have no density to evaluate and keep the default of black.
+    ///
+    /// # Arguments
+    ///
+    /// * `r_in` - The incoming ray
+    /// * `rec` - The hit record containing information about the intersection
+    /// * `scattered` - The outgoing ray
+    ///
+    /// # Returns
+    ///
+    /// The BSDF times cos(theta) for the scattered direction
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> Color {
+        Color::default()
+    }
+
+    /// Returns the probability density with which `scatter` samples a direction.
+    ///
+    /// For materials with a density, `scatter` sets the attenuation to
+    /// `eval / scattering_pdf` for the sampled direction.
+    ///
+    /// # Arguments
+    ///
+    /// * `r_in` - The incoming ray
+    /// * `rec` - The hit record containing information about the intersection
+    /// * `scattered` - The outgoing ray
+    ///
+    /// # Returns
+    ///
+    /// The solid angle density of the scattered direction, or 0.0 for
+    /// materials that scatter into discrete directions
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        0.0
+    }
+
+    /// Returns the radiance emitted by the material towards the incoming ray.
+    ///
+    /// Only light sources emit; other materials keep the default of black.
+    ///
+    /// # Arguments
+    ///
+    /// * `r_in` - The ray arriving at the surface
+    /// * `rec` - The hit record containing information about the intersection
+    ///
+    /// # Returns
+    ///
+    /// The emitted radiance along the reverse of `r_in`
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Color {
+        Color::default()
+    }
+
+    /// Returns true if scattered directions depend on the ray's wavelength.
+    ///
+    /// Spectral rendering uses this to stop tracing the secondary
+    /// wavelengths of a path once it has been split by dispersion.
+    fn is_dispersive(&self) -> bool {
+        false
+    }
 }
 
 /// A diffuse material that scatters light uniformly.
@@ -68,20 +155,148 @@ impl Material for Lambertian {
     /// the normal is used instead to prevent numerical issues.
     fn scatter(
         &self,
-        _r_in: &Ray,
+        r_in: &Ray,
         rec: &HitRecord,
         attenuation: &mut Color,
         scattered: &mut Ray,
-    ) -> bool {
-        let mut scatter_direction = rec.normal + Vec3::random_unit_vector();
+        sampler: &mut dyn Sampler,
+    ) -> Scatter {
+        let mut scatter_direction = rec.normal + Vec3::random_unit_vector(sampler);
 
         if scatter_direction.near_zero() {
             scatter_direction = rec.normal;
         }
 
-        *scattered = Ray::new(rec.p, scatter_direction);
+        *scattered = Ray::with_wavelength(rec.p, scatter_direction, r_in.wavelength());
         *attenuation = self.albedo;
-        true
+        Scatter::Scattered
+    }
+
+    /// Evaluates the Lambertian BSDF, albedo / pi, times the cosine term.
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        self.scattering_pdf(r_in, rec, scattered) * self.albedo
+    }
+
+    /// Returns the cosine-weighted hemisphere density, cos(theta) / pi.
+    fn scattering_pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let cos_theta = rec.normal.dot(&scattered.direction().unit_vector());
+        match cos_theta < 0.0 {
+            true => 0.0,
+            false => cos_theta / PI,
+        }
+    }
+}
+
+/// A rough diffuse material using the Oren-Nayar reflectance model.
+///
+/// Oren-Nayar models the surface as a collection of tiny Lambertian
+/// V-cavities. Compared to `Lambertian`, rough surfaces appear flatter and
+/// brighter towards the viewer, as seen on clay, concrete and cloth. With a
+/// roughness of zero it reduces to the Lambertian model.
+pub struct OrenNayar {
+    /// The color reflectance of the material (0.0 to 1.0 for each component)
+    albedo: Color,
+    /// Oren-Nayar `A` coefficient derived from the roughness
+    a: f64,
+    /// Oren-Nayar `B` coefficient derived from the roughness
+    b: f64,
+}
+
+impl OrenNayar {
+    /// Creates a new Oren-Nayar material with the given albedo and roughness.
+    ///
+    /// # Arguments
+    ///
+    /// * `albedo` - The color reflectance of the material
+    /// * `sigma` - The standard deviation of the microfacet slope angle, in degrees
+    pub fn new(albedo: Color, sigma: f64) -> Self {
+        let sigma = sigma.to_radians();
+        let sigma2 = sigma * sigma;
+
+        Self {
+            albedo,
+            a: 1.0 - sigma2 / (2.0 * (sigma2 + 0.33)),
+            b: 0.45 * sigma2 / (sigma2 + 0.09),
+        }
+    }
+
+    /// Computes the Oren-Nayar factor that scales the Lambertian reflectance.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The surface normal
+    /// * `wo` - The unit direction towards the viewer
+    /// * `wi` - The unit direction towards the light
+    ///
+    /// # Returns
+    ///
+    /// The factor `A + B max(0, cos(phi_i - phi_o)) sin(alpha) tan(beta)`
+    fn factor(&self, n: &Vec3, wo: &Vec3, wi: &Vec3) -> f64 {
+        let cos_theta_i = n.dot(wi).clamp(0.0, 1.0);
+        let cos_theta_o = n.dot(wo).clamp(0.0, 1.0);
+        let sin_theta_i = (1.0 - cos_theta_i * cos_theta_i).sqrt();
+        let sin_theta_o = (1.0 - cos_theta_o * cos_theta_o).sqrt();
+
+        // Cosine of the azimuthal angle between the two directions.
+        let mut max_cos = 0.0;
+        if sin_theta_i > 1.0e-4 && sin_theta_o > 1.0e-4 {
+            let tangent_i = (*wi - cos_theta_i * *n) / sin_theta_i;
+            let tangent_o = (*wo - cos_theta_o * *n) / sin_theta_o;
+            max_cos = f64::max(0.0, tangent_i.dot(&tangent_o));
+        }
+
+        // alpha is the larger of the two polar angles, beta the smaller.
+        let (sin_alpha, tan_beta) = match cos_theta_i > cos_theta_o {
+            true => (sin_theta_o, sin_theta_i / cos_theta_i),
+            false => (sin_theta_i, sin_theta_o / f64::max(cos_theta_o, 1.0e-8)),
+        };
+
+        self.a + self.b * max_cos * sin_alpha * tan_beta
+    }
+}
+
+impl Material for OrenNayar {
+    /// Scatters the ray with the same cosine distribution as `Lambertian`.
+    ///
+    /// The attenuation is the Oren-Nayar BSDF divided by the cosine density,
+    /// which is the albedo scaled by the Oren-Nayar factor.
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
+    ) -> Scatter {
+        let mut scatter_direction = rec.normal + Vec3::random_unit_vector(sampler);
+
+        if scatter_direction.near_zero() {
+            scatter_direction = rec.normal;
+        }
+
+        let wo = -r_in.direction().unit_vector();
+        let wi = scatter_direction.unit_vector();
+
+        *scattered = Ray::with_wavelength(rec.p, scatter_direction, r_in.wavelength());
+        *attenuation = self.factor(&rec.normal, &wo, &wi) * self.albedo;
+        Scatter::Scattered
+    }
+
+    /// Evaluates the Oren-Nayar BSDF times the cosine term.
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let wo = -r_in.direction().unit_vector();
+        let wi = scattered.direction().unit_vector();
+        let factor = self.factor(&rec.normal, &wo, &wi);
+        (factor * self.scattering_pdf(r_in, rec, scattered)) * self.albedo
+    }
+
+    /// Returns the cosine-weighted hemisphere density, cos(theta) / pi.
+    fn scattering_pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let cos_theta = rec.normal.dot(&scattered.direction().unit_vector());
+        match cos_theta < 0.0 {
+            true => 0.0,
+            false => cos_theta / PI,
+        }
     }
 }
 
@@ -122,22 +337,116 @@ impl Material for Metal {
         rec: &HitRecord,
         attenuation: &mut Color,
         scattered: &mut Ray,
-    ) -> bool {
+        sampler: &mut dyn Sampler,
+    ) -> Scatter {
         let reflected = Vec3::reflect(&r_in.direction().unit_vector(), &rec.normal);
 
         *attenuation = self.albedo;
-        *scattered = Ray::new(rec.p, reflected + self.fuzz * Vec3::random_unit_vector());
-        scattered.direction().dot(&rec.normal) > 0.0
+        *scattered = Ray::with_wavelength(
+            rec.p,
+            reflected + self.fuzz * Vec3::random_unit_vector(sampler),
+            r_in.wavelength(),
+        );
+        match scattered.direction().dot(&rec.normal) > 0.0 {
+            true => Scatter::Specular,
+            false => Scatter::Absorbed,
+        }
+    }
+}
+
+/// Wavelength at which dispersive indices of refraction are evaluated for RGB rays.
+///
+/// This is the helium d-line (587.6 nm), the wavelength at which the
+/// refractive index of optical glass is usually quoted.
+const REFERENCE_WAVELENGTH: f64 = 587.6;
+
+/// The index of refraction of a material, possibly varying with wavelength.
+///
+/// Dispersive models take wavelengths in nanometers and internally use
+/// micrometers, the unit in which published coefficients are given.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RefractiveIndex {
+    /// The same index of refraction at every wavelength
+    Constant(f64),
+    /// Cauchy's equation, n = A + B / lambda^2 (lambda in micrometers)
+    Cauchy {
+        /// The constant term A
+        a: f64,
+        /// The dispersion term B in square micrometers
+        b: f64,
+    },
+    /// The Sellmeier equation, n^2 = 1 + sum(B_i lambda^2 / (lambda^2 - C_i))
+    /// (lambda in micrometers)
+    Sellmeier {
+        /// The B coefficients
+        b: [f64; 3],
+        /// The C coefficients in square micrometers
+        c: [f64; 3],
+    },
+}
+
+impl RefractiveIndex {
+    /// Schott N-BK7, a common borosilicate crown glass
+    pub const BK7: Self = Self::Sellmeier {
+        b: [1.039_612_12, 0.231_792_344, 1.010_469_45],
+        c: [0.006_000_698_67, 0.020_017_914_4, 103.560_653],
+    };
+    /// Schott SF11, a dense flint glass with strong dispersion
+    pub const SF11: Self = Self::Sellmeier {
+        b: [1.737_596_95, 0.313_747_346, 1.898_781_01],
+        c: [0.013_188_707, 0.062_306_814_2, 155.236_29],
+    };
+    /// Fused silica (amorphous quartz)
+    pub const FUSED_SILICA: Self = Self::Sellmeier {
+        b: [0.696_166_3, 0.407_942_6, 0.897_479_4],
+        c: [0.004_679_148, 0.013_512_063, 97.934_002_5],
+    };
+    /// Diamond, with its characteristic high index and "fire"
+    pub const DIAMOND: Self = Self::Sellmeier {
+        b: [0.3306, 4.3356, 0.0],
+        c: [0.030_625, 0.011_236, 0.0],
+    };
+
+    /// Returns the index of refraction at the given wavelength.
+    ///
+    /// # Arguments
+    ///
+    /// * `wavelength` - The wavelength in nanometers, or `None` for RGB rays,
+    ///   in which case the index at the d-line (587.6 nm) is returned
+    ///
+    /// # Returns
+    ///
+    /// The index of refraction
+    pub fn at(&self, wavelength: Option<f64>) -> f64 {
+        let lambda = wavelength.unwrap_or(REFERENCE_WAVELENGTH) / 1000.0;
+        let lambda2 = lambda * lambda;
+
+        match self {
+            Self::Constant(n) => *n,
+            Self::Cauchy { a, b } => a + b / lambda2,
+            Self::Sellmeier { b, c } => {
+                let sum: f64 = (0..3).map(|i| b[i] * lambda2 / (lambda2 - c[i])).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
     }
 }
 
 /// A transparent material that refracts light.
 ///
 /// Dielectric materials (like glass) can both reflect and refract light
-/// based on their refractive index and the angle of incidence.
+/// based on their refractive index and the angle of incidence. When the
+/// refractive index depends on wavelength, rays that carry a wavelength are
+/// bent by different amounts, splitting white light into its colors.
+///
+/// An optional absorption coefficient tints light traveling through the
+/// interior following the Beer-Lambert law, so thick glass looks darker
+/// than thin glass.
 pub struct Dielectric {
     /// The refractive index of the material
-    refraction_index: f64,
+    refraction_index: RefractiveIndex,
+    /// The absorption coefficient per unit distance for each color component
+    absorption: Color,
 }
 
 impl Dielectric {
@@ -147,7 +456,54 @@ impl Dielectric {
     ///
     /// * `refraction_index` - The refractive index of the material
     pub fn new(refraction_index: f64) -> Self {
-        Self { refraction_index }
+        Self {
+            refraction_index: RefractiveIndex::Constant(refraction_index),
+            absorption: Color::default(),
+        }
+    }
+
+    /// Creates a new dielectric material with a wavelength-dependent refractive index.
+    ///
+    /// # Arguments
+    ///
+    /// * `refraction_index` - The dispersion model of the material, such as
+    ///   `RefractiveIndex::BK7`
+    pub fn dispersive(refraction_index: RefractiveIndex) -> Self {
+        Self {
+            refraction_index,
+            absorption: Color::default(),
+        }
+    }
+
+    /// Returns this material with the given interior absorption coefficient.
+    ///
+    /// Light that travels a distance `d` inside the material is attenuated
+    /// by `exp(-absorption * d)` for each color component.
+    ///
+    /// # Arguments
+    ///
+    /// * `absorption` - The absorption coefficient per unit distance
+    pub fn with_absorption(mut self, absorption: Color) -> Self {
+        self.absorption = absorption;
+        self
+    }
+
+    /// Returns this material with absorption chosen to reach a color at a given depth.
+    ///
+    /// This is often easier to art-direct than a raw coefficient: light
+    /// that travels `distance` through the interior is tinted to `color`.
+    ///
+    /// # Arguments
+    ///
+    /// * `color` - The transmitted color after `distance` (each component in (0,1\])
+    /// * `distance` - The distance at which the color is reached
+    pub fn with_absorption_color(self, color: Color, distance: f64) -> Self {
+        let coefficient = |c: f64| -f64::ln(c.max(1.0e-6)) / distance;
+        self.with_absorption(Color::new(
+            coefficient(color.x()),
+            coefficient(color.y()),
+            coefficient(color.z()),
+        ))
     }
 
     /// Calculates the reflectance using Schlick's approximation.
@@ -175,29 +531,256 @@ impl Material for Dielectric {
     /// - The angle of incidence
     /// - The refractive indices of the materials
     /// - Fresnel reflection (using Schlick's approximation)
+    ///
+    /// When the ray hits the surface from the inside, it has traveled
+    /// through the interior and is attenuated by the absorption coefficient.
     fn scatter(
         &self,
         r_in: &Ray,
         rec: &HitRecord,
         attenuation: &mut Color,
         scattered: &mut Ray,
-    ) -> bool {
-        *attenuation = Color::new(1.0, 1.0, 1.0);
+        sampler: &mut dyn Sampler,
+    ) -> Scatter {
+        *attenuation = match rec.front_face {
+            true => Color::new(1.0, 1.0, 1.0),
+            false => {
+                let distance = rec.t * r_in.direction().length();
+                Color::new(
+                    f64::exp(-self.absorption.x() * distance),
+                    f64::exp(-self.absorption.y() * distance),
+                    f64::exp(-self.absorption.z() * distance),
+                )
+            }
+        };
+        let refraction_index = self.refraction_index.at(r_in.wavelength());
         let ri = match rec.front_face {
-            true => 1.0 / self.refraction_index,
-            false => self.refraction_index,
+            true => 1.0 / refraction_index,
+            false => refraction_index,
         };
 
         let unit_direction = r_in.direction().unit_vector();
         let cos_theta = f64::min(-unit_direction.dot(&rec.normal), 1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
         let cannot_refract = ri * sin_theta > 1.0;
-        let direction = match cannot_refract || Self::reflectance(cos_theta, ri) > random_double() {
+        let direction = match cannot_refract || Self::reflectance(cos_theta, ri) > sampler.get_1d()
+        {
             true => Vec3::reflect(&unit_direction, &rec.normal),
             false => Vec3::refract(&unit_direction, &rec.normal, ri),
         };
-        *scattered = Ray::new(rec.p, direction);
+        *scattered = Ray::with_wavelength(rec.p, direction, r_in.wavelength());
+
+        Scatter::Specular
+    }
+
+    /// Returns true unless the refractive index is constant.
+    fn is_dispersive(&self) -> bool {
+        !matches!(self.refraction_index, RefractiveIndex::Constant(_))
+    }
+}
+
+/// A phase function that scatters light uniformly in all directions.
+///
+/// Isotropic is used as the material of participating media such as
+/// `ConstantMedium`, where scattering happens inside a volume rather than
+/// at a surface.
+pub struct Isotropic {
+    /// The texture giving the color of the scattered light
+    tex: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    /// Creates a new isotropic phase function with the given albedo.
+    ///
+    /// # Arguments
+    ///
+    /// * `albedo` - The color of the scattered light
+    pub fn new(albedo: Color) -> Self {
+        Self::from_texture(Arc::new(SolidColor::new(albedo)))
+    }
+
+    /// Creates a new isotropic phase function using a texture for its albedo.
+    ///
+    /// # Arguments
+    ///
+    /// * `tex` - The texture giving the color of the scattered light
+    pub fn from_texture(tex: Arc<dyn Texture>) -> Self {
+        Self { tex }
+    }
+}
+
+impl Material for Isotropic {
+    /// Scatters the ray in a uniformly random direction.
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
+    ) -> Scatter {
+        *scattered =
+            Ray::with_wavelength(rec.p, Vec3::random_unit_vector(sampler), r_in.wavelength());
+        *attenuation = self.tex.value(rec.u, rec.v, &rec.p);
+        Scatter::Scattered
+    }
+
+    /// Evaluates the phase function, albedo / (4 pi); volumes have no cosine term.
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        self.scattering_pdf(r_in, rec, scattered) * self.tex.value(rec.u, rec.v, &rec.p)
+    }
+
+    /// Returns the uniform sphere density, 1 / (4 pi).
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+/// An anisotropic phase function following Henyey and Greenstein.
+///
+/// The asymmetry parameter `g` controls the average cosine of the
+/// scattering angle: positive values scatter light forward (as in clouds
+/// and smoke), negative values scatter it back, and zero is isotropic.
+pub struct HenyeyGreenstein {
+    /// The texture giving the color of the scattered light
+    tex: Arc<dyn Texture>,
+    /// The asymmetry parameter, in (-1, 1)
+    g: f64,
+}
+
+impl HenyeyGreenstein {
+    /// Creates a new Henyey-Greenstein phase function.
+    ///
+    /// # Arguments
+    ///
+    /// * `albedo` - The color of the scattered light
+    /// * `g` - The asymmetry parameter (clamped to \[-0.99, 0.99\])
+    pub fn new(albedo: Color, g: f64) -> Self {
+        Self::from_texture(Arc::new(SolidColor::new(albedo)), g)
+    }
+
+    /// Creates a new Henyey-Greenstein phase function using a texture for its albedo.
+    ///
+    /// # Arguments
+    ///
+    /// * `tex` - The texture giving the color of the scattered light
+    /// * `g` - The asymmetry parameter (clamped to \[-0.99, 0.99\])
+    pub fn from_texture(tex: Arc<dyn Texture>, g: f64) -> Self {
+        Self {
+            tex,
+            g: g.clamp(-0.99, 0.99),
+        }
+    }
+
+    /// Evaluates the phase function for the cosine of the scattering angle.
+    ///
+    /// # Arguments
+    ///
+    /// * `cos_theta` - Cosine of the angle between the incoming propagation
+    ///   direction and the scattered direction
+    fn phase(&self, cos_theta: f64) -> f64 {
+        let g2 = self.g * self.g;
+        let denom = 1.0 + g2 - 2.0 * self.g * cos_theta;
+        (1.0 - g2) / (4.0 * PI * denom * denom.sqrt())
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    /// Scatters the ray by importance sampling the phase function.
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
+    ) -> Scatter {
+        let g = self.g;
+        let (u, v) = sampler.get_2d();
+        let cos_theta = match g.abs() < 1.0e-3 {
+            true => 1.0 - 2.0 * u,
+            false => {
+                let sq = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+                ((1.0 + g * g - sq * sq) / (2.0 * g)).clamp(-1.0, 1.0)
+            }
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * v;
 
-        true
+        let uvw = Onb::new(&r_in.direction());
+        let direction = uvw.transform(&Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ));
+
+        *scattered = Ray::with_wavelength(rec.p, direction, r_in.wavelength());
+        *attenuation = self.tex.value(rec.u, rec.v, &rec.p);
+        Scatter::Scattered
+    }
+
+    /// Evaluates the phase function times the albedo; volumes have no cosine term.
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        self.scattering_pdf(r_in, rec, scattered) * self.tex.value(rec.u, rec.v, &rec.p)
+    }
+
+    /// Returns the density of the scattered direction, which equals the phase function.
+    fn scattering_pdf(&self, r_in: &Ray, _rec: &HitRecord, scattered: &Ray) -> f64 {
+        let cos_theta = r_in
+            .direction()
+            .unit_vector()
+            .dot(&scattered.direction().unit_vector());
+        self.phase(cos_theta)
+    }
+}
+
+/// A material that emits light and does not scatter.
+///
+/// Surfaces with this material act as area lights. They emit uniformly
+/// (Lambertian emission) from their front face only.
+pub struct DiffuseLight {
+    /// The texture giving the emitted radiance
+    tex: Arc<dyn Texture>,
+}
+
+impl DiffuseLight {
+    /// Creates a new diffuse light with the given emitted radiance.
+    ///
+    /// # Arguments
+    ///
+    /// * `emit` - The emitted radiance; components may be greater than 1
+    pub fn new(emit: Color) -> Self {
+        Self::from_texture(Arc::new(SolidColor::new(emit)))
+    }
+
+    /// Creates a new diffuse light using a texture for its emitted radiance.
+    ///
+    /// # Arguments
+    ///
+    /// * `tex` - The texture giving the emitted radiance
+    pub fn from_texture(tex: Arc<dyn Texture>) -> Self {
+        Self { tex }
+    }
+}
+
+impl Material for DiffuseLight {
+    /// Absorbs every ray; lights do not reflect.
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        _rec: &HitRecord,
+        _attenuation: &mut Color,
+        _scattered: &mut Ray,
+        _sampler: &mut dyn Sampler,
+    ) -> Scatter {
+        Scatter::Absorbed
+    }
+
+    /// Returns the emitted radiance on the front face and black on the back face.
+    fn emitted(&self, _r_in: &Ray, rec: &HitRecord) -> Color {
+        match rec.front_face {
+            true => self.tex.value(rec.u, rec.v, &rec.p),
+            false => Color::default(),
+        }
     }
 }
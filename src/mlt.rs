@@ -0,0 +1,468 @@
+//! Metropolis light transport for the raytracer.
+//!
+//! This module provides a primary sample space Metropolis light transport
+//! integrator (Kelemen et al. 2002), which is layered over another
+//! integrator such as the path tracer:
+//! - `Mlt`: The integrator, which renders the whole image with Markov chains
+//!
+//! Every camera sample is a function of the random numbers it consumes, its
+//! primary sample. Instead of drawing these numbers independently, `Mlt`
+//! traces camera samples with its own `PrimarySample` sampler and explores the space of primary
+//! samples with Markov chains whose states are visited in proportion to the
+//! brightness of the resulting path. Small steps slightly perturb the
+//! current numbers, so once a chain finds a hard-to-reach light path (light
+//! through a crack in a door, a caustic seen in a mirror) it keeps exploring
+//! its neighborhood; large steps draw fresh numbers so the chains still
+//! cover the whole image.
+//!
+//! The overall brightness of the image is estimated beforehand by a
+//! bootstrap pass of independent samples, which also picks the states the
+//! chains start from.
+
+use std::f64::consts::PI;
+
+use rayon::prelude::*;
+
+use crate::{
+    camera::Camera,
+    hittable::Hittable,
+    integrator::{Integrator, PathTracer},
+    ray::Ray,
+    sampler::{Pcg32, Sampler, hash},
+    spectrum::{SampledSpectrum, SampledWavelengths},
+    vec3::Color,
+};
+
+/// The stream of the generators used for bootstrap samples.
+const BOOTSTRAP_STREAM: u64 = 0;
+/// The stream of the generators used by the Markov chains.
+const CHAIN_STREAM: u64 = 1;
+/// The stream of the generator that picks the starting states of the chains.
+const SELECTION_STREAM: u64 = 2;
+/// The number of groups the chains are split into to run in parallel.
+///
+/// Each group adds its chains to its own image, and the images are summed
+/// in group order, so the result does not depend on the number of threads.
+const CHAIN_GROUPS: u64 = 16;
+
+/// A primary sample space Metropolis light transport integrator.
+///
+/// The image is rendered by `chains` Markov chains that together make as
+/// many mutations as there are camera samples in a regular render. Each
+/// mutation is either a large step, with probability
+/// `large_step_probability`, or a small step that perturbs every random
+/// number by a normal distribution with standard deviation `sigma`.
+/// Both the proposed and the current state contribute to the image at
+/// every step, weighted by the acceptance probability.
+///
+/// The camera rays are traced in RGB, whatever the camera's color mode.
+pub struct Mlt {
+    /// The integrator that computes the color of each path
+    pub integrator: Box<dyn Integrator>,
+    /// The number of independent samples used to estimate the image brightness
+    pub bootstrap_samples: u32,
+    /// The number of Markov chains
+    pub chains: u32,
+    /// The probability that a mutation draws completely new random numbers
+    pub large_step_probability: f64,
+    /// The standard deviation of the perturbation made by a small step
+    pub sigma: f64,
+}
+
+impl Mlt {
+    /// Creates a new Metropolis light transport integrator.
+    ///
+    /// The other settings take their default values.
+    ///
+    /// # Arguments
+    ///
+    /// * `integrator` - The integrator that computes the color of each path
+    pub fn new(integrator: Box<dyn Integrator>) -> Self {
+        Self {
+            integrator,
+            bootstrap_samples: 100_000,
+            chains: 256,
+            large_step_probability: 0.3,
+            sigma: 0.01,
+        }
+    }
+
+    /// Traces the camera sample given by a primary sample.
+    ///
+    /// The first two numbers choose the pixel and the remaining ones are
+    /// consumed by the camera and the integrator.
+    ///
+    /// # Arguments
+    ///
+    /// * `camera` - The camera generating the ray
+    /// * `world` - The scene to trace through
+    /// * `sampler` - The primary sample
+    ///
+    /// # Returns
+    ///
+    /// The index of the pixel in the image and the color of the sample,
+    /// weighted by the camera's reconstruction filter
+    fn evaluate(
+        &self,
+        camera: &Camera,
+        world: &dyn Hittable,
+        sampler: &mut PrimarySample,
+    ) -> (usize, Color) {
+        let width = camera.image_width;
+        let height = camera.image_height();
+        let i = ((sampler.get_1d() * width as f64) as u32).min(width - 1);
+        let j = ((sampler.get_1d() * height as f64) as u32).min(height - 1);
+
+        let (r, weight) = camera.get_ray(i, j, sampler);
+        let color = self
+            .integrator
+            .ray_color(&r, camera.max_depth, world, sampler);
+        (j as usize * width as usize + i as usize, weight * color)
+    }
+
+    /// Returns the brightness a chain distributes its states by.
+    ///
+    /// Samples in the negative lobes of the reconstruction filter have a
+    /// negative color, so the magnitude of the luminance is used.
+    fn importance(color: &Color) -> f64 {
+        let y = color.luminance().abs();
+        match y.is_finite() {
+            true => y,
+            false => 0.0,
+        }
+    }
+
+    /// Runs one Markov chain and adds its contributions to an image.
+    ///
+    /// # Arguments
+    ///
+    /// * `sampler` - The primary sample of the starting state
+    /// * `start` - The pixel index and color of the starting state
+    /// * `mutations` - The number of mutations to make
+    /// * `camera` - The camera generating the rays
+    /// * `world` - The scene to trace through
+    /// * `image` - The image the contributions are added to
+    fn run_chain(
+        &self,
+        mut sampler: PrimarySample,
+        start: (usize, Color),
+        mutations: u64,
+        camera: &Camera,
+        world: &dyn Hittable,
+        image: &mut [Color],
+    ) {
+        let (mut current_pixel, mut current_color) = start;
+        let mut current_importance = Self::importance(&current_color);
+
+        for _ in 0..mutations {
+            sampler.start_iteration();
+            let (proposed_pixel, proposed_color) = self.evaluate(camera, world, &mut sampler);
+            let proposed_importance = Self::importance(&proposed_color);
+
+            let accept = match current_importance > 0.0 {
+                true => (proposed_importance / current_importance).min(1.0),
+                false => 1.0,
+            };
+
+            // Expected values: both states contribute, weighted by how
+            // likely the chain is to move to each of them.
+            if proposed_importance > 0.0 {
+                image[proposed_pixel] += accept / proposed_importance * proposed_color;
+            }
+            if current_importance > 0.0 {
+                image[current_pixel] += (1.0 - accept) / current_importance * current_color;
+            }
+
+            match sampler.rng.next_f64() < accept {
+                true => {
+                    sampler.accept();
+                    current_pixel = proposed_pixel;
+                    current_color = proposed_color;
+                    current_importance = proposed_importance;
+                }
+                false => sampler.reject(),
+            }
+        }
+    }
+}
+
+impl Default for Mlt {
+    /// Creates a Metropolis light transport integrator over the path tracer.
+    fn default() -> Self {
+        Self::new(Box::new(PathTracer))
+    }
+}
+
+impl Integrator for Mlt {
+    /// Computes the color of a ray with the underlying integrator.
+    fn ray_color(
+        &self,
+        r: &Ray,
+        depth: u32,
+        world: &dyn Hittable,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        self.integrator.ray_color(r, depth, world, sampler)
+    }
+
+    /// Prepares the underlying integrator.
+    fn preprocess(&mut self, world: &dyn Hittable, seed: u64) {
+        self.integrator.preprocess(world, seed);
+    }
+
+    /// Computes the spectral radiance of a ray with the underlying integrator.
+    fn ray_color_spectral(
+        &self,
+        r: &Ray,
+        lambda: &mut SampledWavelengths,
+        depth: u32,
+        world: &dyn Hittable,
+        sampler: &mut dyn Sampler,
+    ) -> SampledSpectrum {
+        self.integrator
+            .ray_color_spectral(r, lambda, depth, world, sampler)
+    }
+
+    /// Renders the image with Markov chains started from a bootstrap pass.
+    ///
+    /// Every random number is derived from the camera's seed.
+    fn render_image(&self, camera: &Camera, world: &dyn Hittable) -> Option<Vec<Color>> {
+        let width = camera.image_width as usize;
+        let pixels = width * camera.image_height() as usize;
+        let seed = camera.seed;
+        let new_sampler = |index: u64| PrimarySample {
+            rng: Pcg32::new(hash(&[seed, index]), BOOTSTRAP_STREAM),
+            values: Vec::new(),
+            index: 0,
+            iteration: 0,
+            last_large_step: 0,
+            large_step: true,
+            large_step_probability: self.large_step_probability,
+            sigma: self.sigma,
+        };
+
+        // Estimate the brightness of the image with independent samples.
+        eprintln!("Bootstrapping with {} samples", self.bootstrap_samples);
+        let bootstrap_samples = self.bootstrap_samples.max(1) as u64;
+        let importances: Vec<f64> = (0..bootstrap_samples)
+            .into_par_iter()
+            .map(|index| {
+                let (_, color) = self.evaluate(camera, world, &mut new_sampler(index));
+                Self::importance(&color)
+            })
+            .collect();
+
+        let mut cdf = Vec::with_capacity(importances.len());
+        let mut total = 0.0;
+        for importance in &importances {
+            total += importance;
+            cdf.push(total);
+        }
+        if total <= 0.0 {
+            return Some(vec![Color::default(); pixels]);
+        }
+        let brightness = total / bootstrap_samples as f64;
+
+        // Start each chain from a bootstrap sample chosen in proportion to
+        // its brightness, so the chains begin in their stationary distribution.
+        let chains = self.chains.max(1) as u64;
+        let mut rng = Pcg32::new(seed, SELECTION_STREAM);
+        let starts: Vec<u64> = (0..chains)
+            .map(|_| {
+                let target = rng.next_f64() * total;
+                cdf.partition_point(|&c| c <= target)
+                    .min(importances.len() - 1) as u64
+            })
+            .collect();
+
+        let total_mutations = camera.samples_per_pixel as u64 * pixels as u64;
+        eprintln!("Running {} chains", chains);
+        let group_size = chains.div_ceil(CHAIN_GROUPS);
+        let group_images: Vec<Vec<Color>> = starts
+            .par_chunks(group_size as usize)
+            .enumerate()
+            .map(|(group, starts)| {
+                let mut image = vec![Color::default(); pixels];
+                for (offset, &start) in starts.iter().enumerate() {
+                    let chain = group as u64 * group_size + offset as u64;
+                    let mutations =
+                        total_mutations / chains + u64::from(chain < total_mutations % chains);
+
+                    // Replay the bootstrap sample to recover its random
+                    // numbers, then give the chain its own generator.
+                    let mut sampler = new_sampler(start);
+                    let state = self.evaluate(camera, world, &mut sampler);
+                    sampler.rng = Pcg32::new(hash(&[seed, chain]), CHAIN_STREAM);
+
+                    self.run_chain(sampler, state, mutations, camera, world, &mut image);
+                }
+                image
+            })
+            .collect();
+
+        let mut image = vec![Color::default(); pixels];
+        for group_image in group_images {
+            for (a, b) in image.iter_mut().zip(group_image) {
+                *a += b;
+            }
+        }
+
+        // Each pixel received on average samples_per_pixel mutations, each
+        // contributing color / importance.
+        let scale = brightness / camera.samples_per_pixel.max(1) as f64;
+        Some(image.into_iter().map(|color| scale * color).collect())
+    }
+
+    /// Names the integrator, the integrator it explores paths with and the mutation settings.
+    fn description(&self) -> String {
+        format!(
+            "metropolis over {}, {} bootstrap samples, {} chains, large step probability {}, sigma {}",
+            self.integrator.description(),
+            self.bootstrap_samples,
+            self.chains,
+            self.large_step_probability,
+            self.sigma
+        )
+    }
+}
+
+/// A random number in a primary sample.
+#[derive(Debug, Clone, Copy)]
+struct PrimaryValue {
+    /// The current value
+    value: f64,
+    /// The iteration the value was last changed in
+    last_modified: u64,
+    /// The value before the current iteration's mutation
+    backup: f64,
+    /// The iteration `last_modified` had before the current iteration's mutation
+    backup_modified: u64,
+}
+
+/// A sampler whose random numbers are mutated by Metropolis steps.
+///
+/// Every value is used at most once per iteration and is mutated lazily,
+/// when it is used: a value that was last touched several small steps ago
+/// receives all the perturbations it missed at once, and a value untouched
+/// since the last accepted large step is drawn afresh.
+#[derive(Debug, Clone)]
+struct PrimarySample {
+    /// The generator for new values, perturbations and acceptance tests
+    rng: Pcg32,
+    /// The random numbers of the current state
+    values: Vec<PrimaryValue>,
+    /// The index of the next value handed out
+    index: usize,
+    /// The number of mutations started so far
+    iteration: u64,
+    /// The iteration of the last accepted large step
+    last_large_step: u64,
+    /// Whether the current mutation is a large step
+    large_step: bool,
+    /// The probability that a mutation is a large step
+    large_step_probability: f64,
+    /// The standard deviation of a small step
+    sigma: f64,
+}
+
+impl PrimarySample {
+    /// Starts a new mutation.
+    fn start_iteration(&mut self) {
+        self.iteration += 1;
+        self.large_step = self.rng.next_f64() < self.large_step_probability;
+        self.index = 0;
+    }
+
+    /// Keeps the mutated values.
+    fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step = self.iteration;
+        }
+    }
+
+    /// Restores the values from before the mutation.
+    fn reject(&mut self) {
+        for v in &mut self.values {
+            if v.last_modified == self.iteration {
+                v.value = v.backup;
+                v.last_modified = v.backup_modified;
+            }
+        }
+        self.iteration -= 1;
+    }
+
+    /// Returns a normally distributed number with mean 0 and standard deviation 1.
+    fn normal(&mut self) -> f64 {
+        // Box-Muller transform.
+        let u1 = 1.0 - self.rng.next_f64();
+        let u2 = self.rng.next_f64();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+    }
+}
+
+impl Sampler for PrimarySample {
+    /// Does nothing; the chain chooses the pixel from the primary sample itself.
+    fn start_pixel_sample(&mut self, _i: u32, _j: u32, _sample_index: u32) {}
+
+    /// Returns the next value, mutated for the current iteration.
+    fn get_1d(&mut self) -> f64 {
+        let index = self.index;
+        self.index += 1;
+        if index >= self.values.len() {
+            // A value the current state never used is uniformly distributed,
+            // whatever the kind of step. Its backup is the same value, so a
+            // rejected mutation keeps it.
+            let value = self.rng.next_f64();
+            self.values.push(PrimaryValue {
+                value,
+                last_modified: self.iteration,
+                backup: value,
+                backup_modified: self.iteration.saturating_sub(1),
+            });
+            return value;
+        }
+
+        let mut v = self.values[index];
+        if v.last_modified < self.last_large_step {
+            // Untouched since the last accepted large step, which would
+            // have replaced it.
+            v.value = self.rng.next_f64();
+            v.last_modified = self.last_large_step;
+        }
+
+        v.backup = v.value;
+        v.backup_modified = v.last_modified;
+        match self.large_step {
+            true => v.value = self.rng.next_f64(),
+            false => {
+                let missed_steps = (self.iteration - v.last_modified) as f64;
+                let step = self.sigma * missed_steps.sqrt() * self.normal();
+                v.value = (v.value + step).rem_euclid(1.0);
+                // rem_euclid can round up to exactly 1 for tiny negative values.
+                if v.value >= 1.0 {
+                    v.value = 0.0;
+                }
+            }
+        }
+        v.last_modified = self.iteration;
+
+        self.values[index] = v;
+        v.value
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+
+    /// Returns a copy of the primary sample; its values do not depend on a seed.
+    fn clone_with_seed(&self, _seed: u64) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "primary sample space, large step probability {}, sigma {}",
+            self.large_step_probability, self.sigma
+        )
+    }
+}
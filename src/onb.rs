@@ -0,0 +1,65 @@
+//! Orthonormal basis for the raytracer.
+//!
+//! This module provides an `Onb` struct that builds a local coordinate frame
+//! around a direction (usually a surface normal). Materials use it to sample
+//! directions in a frame where the normal is the z axis and then transform
+//! them back into world space.
+
+use crate::vec3::Vec3;
+
+/// An orthonormal basis built around a single direction.
+///
+/// The basis vectors u, v, w are mutually perpendicular unit vectors, with
+/// w aligned to the direction the basis was built from.
+#[derive(Debug, Clone, Copy)]
+pub struct Onb {
+    /// The three basis vectors u, v, w
+    axis: [Vec3; 3],
+}
+
+impl Onb {
+    /// Creates a new orthonormal basis whose w axis is aligned with `n`.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The direction to align the w axis with (need not be unit length)
+    pub fn new(n: &Vec3) -> Self {
+        let w = n.unit_vector();
+        let a = match f64::abs(w.x()) > 0.9 {
+            true => Vec3::new(0.0, 1.0, 0.0),
+            false => Vec3::new(1.0, 0.0, 0.0),
+        };
+        let v = w.cross(&a).unit_vector();
+        let u = w.cross(&v);
+
+        Self { axis: [u, v, w] }
+    }
+
+    /// Returns the u basis vector
+    pub fn u(&self) -> Vec3 {
+        self.axis[0]
+    }
+
+    /// Returns the v basis vector
+    pub fn v(&self) -> Vec3 {
+        self.axis[1]
+    }
+
+    /// Returns the w basis vector (the direction the basis was built from)
+    pub fn w(&self) -> Vec3 {
+        self.axis[2]
+    }
+
+    /// Transforms a vector from basis coordinates into world space.
+    ///
+    /// # Arguments
+    ///
+    /// * `v` - The vector in basis coordinates
+    ///
+    /// # Returns
+    ///
+    /// The vector expressed in world coordinates
+    pub fn transform(&self, v: &Vec3) -> Vec3 {
+        (v.x() * self.axis[0]) + (v.y() * self.axis[1]) + (v.z() * self.axis[2])
+    }
+}
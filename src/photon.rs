@@ -0,0 +1,416 @@
+//! Photon mapping for the raytracer.
+//!
+//! This module provides a two-pass photon mapping integrator aimed at
+//! caustics, the focused light seen under glass and next to metal:
+//! - `Photon`: A packet of light stored where it landed on a diffuse surface
+//! - `PhotonMap`: A kd-tree of photons supporting radius queries
+//! - `PhotonMapping`: The integrator, which emits photons from the lights in
+//!   a first pass and gathers them while path tracing in the second
+//!
+//! Only caustic photons are stored: those that left a light and reached a
+//! diffuse surface through one or more `Dielectric` or `Metal` bounces. All
+//! other light is path traced as usual. The photon pass is repeated for a
+//! number of iterations with a shrinking gather radius (progressive photon
+//! mapping), so the blur of the density estimate vanishes as more
+//! iterations are used.
+//!
+//! Photons are gathered with a surface density estimate, so scenes with
+//! participating media should use another integrator.
+
+use std::f64::consts::PI;
+
+use rayon::prelude::*;
+
+use crate::{
+    hittable::{HitRecord, Hittable},
+    hittable_list::HittableList,
+    integrator::{Integrator, background},
+    interval::Interval,
+    material::Scatter,
+    ray::Ray,
+    sampler::{IndependentSampler, Sampler},
+    vec3::{Color, Point3, Vec3},
+};
+
+/// Maximum number of specular bounces followed by a photon.
+const MAX_PHOTON_BOUNCES: u32 = 16;
+
+/// A packet of light that landed on a diffuse surface.
+#[derive(Debug, Clone, Copy)]
+pub struct Photon {
+    /// Where the photon landed
+    pub p: Point3,
+    /// The direction the photon was traveling in
+    pub direction: Vec3,
+    /// The flux carried by the photon
+    pub power: Color,
+}
+
+/// A kd-tree of photons.
+///
+/// The tree is stored implicitly: the photons of every subtree occupy a
+/// contiguous range, with the median of the range as the splitting node and
+/// the two halves as its children.
+pub struct PhotonMap {
+    /// The photons in tree order
+    photons: Vec<Photon>,
+    /// The splitting axis of the node at each index
+    axes: Vec<u8>,
+}
+
+impl PhotonMap {
+    /// Builds a kd-tree from a set of photons.
+    ///
+    /// # Arguments
+    ///
+    /// * `photons` - The photons to store
+    pub fn new(mut photons: Vec<Photon>) -> Self {
+        let mut axes = vec![0; photons.len()];
+        Self::build(&mut photons, &mut axes);
+        Self { photons, axes }
+    }
+
+    /// Returns the number of photons in the map
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    /// Returns true if the map has no photons
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+
+    /// Calls a function for every photon within a distance of a point.
+    ///
+    /// # Arguments
+    ///
+    /// * `p` - The center of the query
+    /// * `radius` - The query radius
+    /// * `f` - The function to call for each photon found
+    pub fn for_each_within<F: FnMut(&Photon)>(&self, p: &Point3, radius: f64, mut f: F) {
+        Self::query(&self.photons, &self.axes, p, radius * radius, &mut f);
+    }
+
+    /// Recursively splits a range of photons at the median of its widest axis.
+    fn build(photons: &mut [Photon], axes: &mut [u8]) {
+        if photons.len() <= 1 {
+            return;
+        }
+
+        let mut min = photons[0].p;
+        let mut max = photons[0].p;
+        for photon in photons.iter() {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(photon.p[axis]);
+                max[axis] = max[axis].max(photon.p[axis]);
+            }
+        }
+        let extent = max - min;
+        let axis = match (extent.x() > extent.y(), extent.x() > extent.z()) {
+            (true, true) => 0,
+            _ if extent.y() > extent.z() => 1,
+            _ => 2,
+        };
+
+        let mid = photons.len() / 2;
+        photons.select_nth_unstable_by(mid, |a, b| a.p[axis].total_cmp(&b.p[axis]));
+        axes[mid] = axis as u8;
+
+        let (left, right) = photons.split_at_mut(mid);
+        let (left_axes, right_axes) = axes.split_at_mut(mid);
+        Self::build(left, left_axes);
+        Self::build(&mut right[1..], &mut right_axes[1..]);
+    }
+
+    /// Recursively visits the photons of a subtree within a squared radius of a point.
+    fn query<F: FnMut(&Photon)>(
+        photons: &[Photon],
+        axes: &[u8],
+        p: &Point3,
+        radius_squared: f64,
+        f: &mut F,
+    ) {
+        if photons.is_empty() {
+            return;
+        }
+
+        let mid = photons.len() / 2;
+        let photon = &photons[mid];
+        if (photon.p - *p).length_squared() <= radius_squared {
+            f(photon);
+        }
+        if photons.len() == 1 {
+            return;
+        }
+
+        let axis = axes[mid] as usize;
+        let offset = p[axis] - photon.p[axis];
+        let (left, right) = (&photons[..mid], &photons[mid + 1..]);
+        let (left_axes, right_axes) = (&axes[..mid], &axes[mid + 1..]);
+        let (near, near_axes, far, far_axes) = match offset <= 0.0 {
+            true => (left, left_axes, right, right_axes),
+            false => (right, right_axes, left, left_axes),
+        };
+
+        Self::query(near, near_axes, p, radius_squared, f);
+        if offset * offset <= radius_squared {
+            Self::query(far, far_axes, p, radius_squared, f);
+        }
+    }
+}
+
+/// A progressive photon mapping integrator for caustics.
+///
+/// Before rendering, `iterations` caustic photon maps are built, each from
+/// `photons_per_iteration` photons emitted from the lights. The gather
+/// radius of iteration `i` shrinks following Knaus and Zwicker:
+/// r(i+1)^2 = r(i)^2 (i + alpha) / (i + 1). Each camera sample gathers from
+/// one randomly chosen map, so the image averages over all iterations.
+///
+/// Camera paths are path traced; at every surface with a diffuse
+/// component, the caustic photons around the hit are added, and emitters
+/// reached from there through specular bounces only are skipped since the
+/// photons already account for them.
+pub struct PhotonMapping {
+    /// The emitting objects photons are emitted from
+    pub lights: HittableList,
+    /// The number of photons emitted for each iteration
+    pub photons_per_iteration: usize,
+    /// The number of photon maps, each with a smaller radius
+    pub iterations: usize,
+    /// The gather radius of the first iteration
+    pub initial_radius: f64,
+    /// The fraction of photons kept when the radius shrinks, in (0,1)
+    pub alpha: f64,
+
+    /// The photon map and gather radius of each iteration
+    maps: Vec<(PhotonMap, f64)>,
+}
+
+impl PhotonMapping {
+    /// Creates a new photon mapping integrator with default settings.
+    ///
+    /// The defaults are 16 iterations of 100,000 photons, an initial radius
+    /// of 0.05 and an alpha of 0.7.
+    ///
+    /// # Arguments
+    ///
+    /// * `lights` - The emitting objects photons are emitted from; they
+    ///   should also be added to the scene
+    pub fn new(lights: HittableList) -> Self {
+        Self {
+            lights,
+            photons_per_iteration: 100_000,
+            iterations: 16,
+            initial_radius: 0.05,
+            alpha: 0.7,
+            maps: Vec::new(),
+        }
+    }
+
+    /// Traces one photon from the lights, storing it wherever it lands as a caustic.
+    ///
+    /// The photon is stored at every surface with a diffuse component that
+    /// it reaches after one or more specular bounces, and followed until it
+    /// scatters diffusely.
+    ///
+    /// # Arguments
+    ///
+    /// * `world` - The scene to trace through
+    /// * `photon_count` - The number of photons emitted in the iteration
+    /// * `photons` - Receives the stored photons
+    /// * `sampler` - The source of random numbers for the photon's path
+    fn trace_photon(
+        &self,
+        world: &dyn Hittable,
+        photon_count: usize,
+        photons: &mut Vec<Photon>,
+        sampler: &mut dyn Sampler,
+    ) {
+        let Some(sample) = self.lights.sample_surface(sampler) else {
+            return;
+        };
+        let Some(light) = sample.rec.mat.as_ref() else {
+            return;
+        };
+        if sample.pdf <= 0.0 {
+            return;
+        }
+
+        // Emit with a cosine distribution around the outward normal.
+        let normal = sample.rec.normal;
+        let mut direction = normal + Vec3::random_unit_vector(sampler);
+        if direction.near_zero() {
+            direction = normal;
+        }
+        let mut ray = Ray::new(sample.rec.p, direction);
+        let emitted = light.emitted(&Ray::new(sample.rec.p + direction, -direction), &sample.rec);
+
+        // Le cos / (pdf_area pdf_dir) with pdf_dir = cos / pi.
+        let mut power = emitted * (PI / (sample.pdf * photon_count as f64));
+        for specular_bounces in 0..MAX_PHOTON_BOUNCES {
+            let Some(rec) = world.hit(&ray, Interval::new(0.001, f64::INFINITY), sampler) else {
+                return;
+            };
+            let mat = rec.mat.as_ref().unwrap();
+
+            if specular_bounces > 0 && has_diffuse_component(&ray, &rec) {
+                photons.push(Photon {
+                    p: rec.p,
+                    direction: ray.direction().unit_vector(),
+                    power,
+                });
+            }
+
+            let mut scattered = Ray::default();
+            let mut attenuation = Color::default();
+            if mat.scatter(&ray, &rec, &mut attenuation, &mut scattered, sampler)
+                != Scatter::Specular
+            {
+                return;
+            }
+
+            power = power * attenuation;
+            ray = scattered;
+        }
+    }
+
+    /// Estimates the caustic radiance leaving a hit towards the incoming ray.
+    fn caustics(&self, map: &(PhotonMap, f64), r: &Ray, rec: &HitRecord) -> Color {
+        let (photons, radius) = map;
+        let mat = rec.mat.as_ref().unwrap();
+
+        let mut flux = Color::default();
+        photons.for_each_within(&rec.p, *radius, |photon| {
+            let to_light = -photon.direction;
+            let cos_theta = rec.normal.dot(&to_light);
+            if cos_theta <= 0.0 {
+                return;
+            }
+
+            let scattered = Ray::with_wavelength(rec.p, to_light, r.wavelength());
+            flux += mat.eval(r, rec, &scattered) / cos_theta * photon.power;
+        });
+
+        flux / (PI * radius * radius)
+    }
+}
+
+impl Integrator for PhotonMapping {
+    /// Builds the photon map of every iteration.
+    fn preprocess(&mut self, world: &dyn Hittable, seed: u64) {
+        let mut radius = self.initial_radius;
+        let mut maps = Vec::with_capacity(self.iterations);
+        for iteration in 1..=self.iterations {
+            let photons: Vec<Photon> = (0..self.photons_per_iteration)
+                .into_par_iter()
+                .flat_map_iter(|index| {
+                    // Every photon has its own random numbers, so the maps
+                    // do not depend on how the photons are scheduled.
+                    let mut sampler = IndependentSampler::new(seed);
+                    sampler.start_pixel_sample(iteration as u32, 0, index as u32);
+
+                    let mut photons = Vec::new();
+                    self.trace_photon(
+                        world,
+                        self.photons_per_iteration,
+                        &mut photons,
+                        &mut sampler,
+                    );
+                    photons
+                })
+                .collect();
+            maps.push((PhotonMap::new(photons), radius));
+
+            let i = iteration as f64;
+            radius *= f64::sqrt((i + self.alpha) / (i + 1.0));
+        }
+
+        self.maps = maps;
+    }
+
+    /// Path traces a ray, gathering caustic photons at diffuse surfaces.
+    fn ray_color(
+        &self,
+        r: &Ray,
+        depth: u32,
+        world: &dyn Hittable,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let map = match self.maps.is_empty() {
+            true => None,
+            false => {
+                let index = (sampler.get_1d() * self.maps.len() as f64) as usize;
+                Some(&self.maps[index.min(self.maps.len() - 1)])
+            }
+        };
+
+        let mut radiance = Color::default();
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut ray = *r;
+        // Number of specular bounces since the path left a diffuse surface,
+        // where caustics were gathered.
+        let mut specular_since_gather: Option<u32> = None;
+
+        for _ in 0..depth {
+            let Some(rec) = world.hit(&ray, Interval::new(0.001, f64::INFINITY), sampler) else {
+                return radiance + throughput * background(&ray);
+            };
+
+            let mat = rec.mat.as_ref().unwrap();
+            // Emitters reached from a gather point through specular bounces
+            // are already counted by the photons.
+            let counted_by_photons = matches!(specular_since_gather, Some(n) if n > 0);
+            if !counted_by_photons {
+                radiance += throughput * mat.emitted(&ray, &rec);
+            }
+
+            let gathered = match map {
+                Some(map) if has_diffuse_component(&ray, &rec) => {
+                    radiance += throughput * self.caustics(map, &ray, &rec);
+                    true
+                }
+                _ => false,
+            };
+
+            let mut scattered = Ray::default();
+            let mut attenuation = Color::default();
+            let diffuse_bounce =
+                match mat.scatter(&ray, &rec, &mut attenuation, &mut scattered, sampler) {
+                    Scatter::Absorbed => break,
+                    Scatter::Scattered => true,
+                    Scatter::Specular => false,
+                };
+            specular_since_gather = match (diffuse_bounce, specular_since_gather) {
+                (true, _) if gathered => Some(0),
+                (true, _) => None,
+                (false, Some(n)) => Some(n + 1),
+                (false, None) => None,
+            };
+
+            throughput = throughput * attenuation;
+            ray = scattered;
+        }
+
+        radiance
+    }
+
+    /// Names the integrator and its photon budget and radius settings.
+    fn description(&self) -> String {
+        format!(
+            "photon mapping, {} photons per iteration, {} iterations, initial radius {}, alpha {}",
+            self.photons_per_iteration, self.iterations, self.initial_radius, self.alpha
+        )
+    }
+}
+
+/// Returns true if the material at a hit scatters part of the light diffusely.
+///
+/// The material is probed for the density of scattering along the normal,
+/// which is zero for materials that only scatter into discrete directions.
+fn has_diffuse_component(r: &Ray, rec: &HitRecord) -> bool {
+    let probe = Ray::with_wavelength(rec.p, rec.normal, r.wavelength());
+    rec.mat
+        .as_ref()
+        .is_some_and(|mat| mat.scattering_pdf(r, rec, &probe) > 0.0)
+}
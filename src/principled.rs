@@ -0,0 +1,474 @@
+//! Principled (Disney-style) uber material for the raytracer.
+//!
+//! This module provides a `Principled` material that layers several lobes
+//! controlled by artist-friendly parameters, following the Disney principled
+//! BRDF and the glTF metallic-roughness model:
+//! - A diffuse base with retro-reflection at grazing angles
+//! - A sheen lobe for cloth-like rim highlights
+//! - A GGX microfacet specular lobe that blends from dielectric to metal
+//! - A GGX clearcoat layer with a fixed index of refraction of 1.5
+//! - A smooth transmission lobe for glass-like surfaces
+//!
+//! Every parameter is a texture, so any of them may vary across a surface.
+
+use std::{f64::consts::PI, sync::Arc};
+
+use crate::{
+    hittable::HitRecord,
+    material::{Material, Scatter},
+    onb::Onb,
+    ray::Ray,
+    sampler::Sampler,
+    texture::{ChannelTexture, SolidColor, Texture},
+    vec3::{Color, Vec3},
+};
+
+/// Smallest GGX roughness used, to keep the distribution numerically stable.
+const MIN_ALPHA: f64 = 0.001;
+
+/// A layered physically-based material with Disney-style parameters.
+///
+/// The material is configured through its public fields, in the same way as
+/// the camera. Scalar parameters are read from the red channel of their
+/// texture; use `SolidColor::gray` for constant values.
+pub struct Principled {
+    /// The base color of the surface (diffuse albedo, or metal reflectance)
+    pub base_color: Arc<dyn Texture>,
+    /// Blend between a dielectric (0.0) and a metallic (1.0) surface
+    pub metallic: Arc<dyn Texture>,
+    /// Perceptual roughness of the specular and diffuse lobes (0.0 to 1.0)
+    pub roughness: Arc<dyn Texture>,
+    /// Dielectric specular amount; 0.5 corresponds to an index of refraction of 1.5
+    pub specular: Arc<dyn Texture>,
+    /// Strength of the clearcoat layer (0.0 to 1.0)
+    pub clearcoat: Arc<dyn Texture>,
+    /// Perceptual roughness of the clearcoat layer (0.0 to 1.0)
+    pub clearcoat_roughness: Arc<dyn Texture>,
+    /// Strength of the sheen lobe (0.0 to 1.0)
+    pub sheen: Arc<dyn Texture>,
+    /// Blend of the sheen color from white (0.0) to the base color hue (1.0)
+    pub sheen_tint: Arc<dyn Texture>,
+    /// Fraction of the dielectric base that transmits light (0.0 to 1.0)
+    pub transmission: Arc<dyn Texture>,
+    /// Index of refraction used by the transmission lobe
+    pub ior: f64,
+}
+
+/// Material parameters evaluated at a single hit point.
+struct ShadingParams {
+    /// Base color
+    base_color: Color,
+    /// Metalness
+    metallic: f64,
+    /// Perceptual roughness
+    roughness: f64,
+    /// GGX roughness of the specular lobe
+    alpha: f64,
+    /// Normal-incidence reflectance of the specular lobe
+    specular_f0: Color,
+    /// Clearcoat strength
+    clearcoat: f64,
+    /// GGX roughness of the clearcoat lobe
+    clearcoat_alpha: f64,
+    /// Sheen color, already scaled by the sheen strength
+    sheen_color: Color,
+    /// Transmission amount
+    transmission: f64,
+}
+
+impl ShadingParams {
+    /// Returns the probability of choosing the transmission lobe.
+    fn transmission_weight(&self) -> f64 {
+        (1.0 - self.metallic) * self.transmission
+    }
+
+    /// Returns the weight of the diffuse and sheen lobes.
+    fn diffuse_weight(&self) -> f64 {
+        (1.0 - self.metallic) * (1.0 - self.transmission)
+    }
+
+    /// Returns the normalized sampling probabilities of the reflective lobes.
+    ///
+    /// # Returns
+    ///
+    /// The probabilities of sampling the diffuse, specular and clearcoat lobes
+    fn lobe_probabilities(&self) -> (f64, f64, f64) {
+        let diffuse = self.diffuse_weight();
+        let specular = 1.0;
+        let clearcoat = 0.25 * self.clearcoat;
+        let total = diffuse + specular + clearcoat;
+
+        (diffuse / total, specular / total, clearcoat / total)
+    }
+}
+
+impl Default for Principled {
+    /// Creates a gray, moderately rough dielectric material.
+    fn default() -> Self {
+        Self {
+            base_color: Arc::new(SolidColor::gray(0.8)),
+            metallic: Arc::new(SolidColor::gray(0.0)),
+            roughness: Arc::new(SolidColor::gray(0.5)),
+            specular: Arc::new(SolidColor::gray(0.5)),
+            clearcoat: Arc::new(SolidColor::gray(0.0)),
+            clearcoat_roughness: Arc::new(SolidColor::gray(0.03)),
+            sheen: Arc::new(SolidColor::gray(0.0)),
+            sheen_tint: Arc::new(SolidColor::gray(0.5)),
+            transmission: Arc::new(SolidColor::gray(0.0)),
+            ior: 1.5,
+        }
+    }
+}
+
+impl Principled {
+    /// Creates a new principled material with the given base color texture.
+    ///
+    /// All other parameters take their default values.
+    ///
+    /// # Arguments
+    ///
+    /// * `base_color` - The base color texture
+    pub fn new(base_color: Arc<dyn Texture>) -> Self {
+        Self {
+            base_color,
+            ..Default::default()
+        }
+    }
+
+    /// Creates a new principled material from glTF-style metallic-roughness values.
+    ///
+    /// # Arguments
+    ///
+    /// * `base_color` - The base color factor
+    /// * `metallic` - The metallic factor (0.0 to 1.0)
+    /// * `roughness` - The roughness factor (0.0 to 1.0)
+    pub fn from_metallic_roughness(base_color: Color, metallic: f64, roughness: f64) -> Self {
+        Self {
+            base_color: Arc::new(SolidColor::new(base_color)),
+            metallic: Arc::new(SolidColor::gray(metallic)),
+            roughness: Arc::new(SolidColor::gray(roughness)),
+            ..Default::default()
+        }
+    }
+
+    /// Creates a new principled material from glTF-style textures.
+    ///
+    /// Following the glTF convention, roughness is read from the green
+    /// channel and metalness from the blue channel of the packed texture.
+    ///
+    /// # Arguments
+    ///
+    /// * `base_color` - The base color texture
+    /// * `metallic_roughness` - The packed metallic-roughness texture
+    pub fn from_gltf_textures(
+        base_color: Arc<dyn Texture>,
+        metallic_roughness: Arc<dyn Texture>,
+    ) -> Self {
+        Self {
+            base_color,
+            metallic: Arc::new(ChannelTexture::new(metallic_roughness.clone(), 2)),
+            roughness: Arc::new(ChannelTexture::new(metallic_roughness, 1)),
+            ..Default::default()
+        }
+    }
+
+    /// Evaluates all parameter textures at a hit point.
+    fn shading_params(&self, rec: &HitRecord) -> ShadingParams {
+        let scalar =
+            |texture: &Arc<dyn Texture>| texture.value(rec.u, rec.v, &rec.p).x().clamp(0.0, 1.0);
+
+        let base_color = self.base_color.value(rec.u, rec.v, &rec.p);
+        let metallic = scalar(&self.metallic);
+        let roughness = scalar(&self.roughness);
+        let specular = scalar(&self.specular);
+        let clearcoat_roughness = scalar(&self.clearcoat_roughness);
+        let sheen = scalar(&self.sheen);
+        let sheen_tint = scalar(&self.sheen_tint);
+
+        // Hue and saturation of the base color, normalized to unit luminance.
+        let luminance = base_color.luminance();
+        let tint = match luminance > 0.0 {
+            true => base_color / luminance,
+            false => Color::new(1.0, 1.0, 1.0),
+        };
+
+        let white = Color::new(1.0, 1.0, 1.0);
+        let dielectric_f0 = 0.08 * specular * white;
+
+        ShadingParams {
+            base_color,
+            metallic,
+            roughness,
+            alpha: f64::max(MIN_ALPHA, roughness * roughness),
+            specular_f0: lerp(dielectric_f0, base_color, metallic),
+            clearcoat: scalar(&self.clearcoat),
+            clearcoat_alpha: f64::max(MIN_ALPHA, clearcoat_roughness * clearcoat_roughness),
+            sheen_color: sheen * lerp(white, tint, sheen_tint),
+            transmission: scalar(&self.transmission),
+        }
+    }
+
+    /// Evaluates the reflective lobes multiplied by the cosine term.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The shading parameters at the hit point
+    /// * `n` - The shading normal, facing the viewer
+    /// * `wo` - The unit direction towards the viewer
+    /// * `wi` - The unit direction towards the light
+    ///
+    /// # Returns
+    ///
+    /// The value of BSDF times cos(theta_i), or black below the surface
+    fn eval_reflection(params: &ShadingParams, n: &Vec3, wo: &Vec3, wi: &Vec3) -> Color {
+        let n_dot_l = n.dot(wi);
+        let n_dot_v = n.dot(wo);
+        if n_dot_l <= 0.0 || n_dot_v <= 0.0 {
+            return Color::default();
+        }
+
+        let h = (*wi + *wo).unit_vector();
+        let n_dot_h = n.dot(&h);
+        let l_dot_h = wi.dot(&h);
+
+        // Diffuse with grazing retro-reflection, plus sheen.
+        let fl = schlick_weight(n_dot_l);
+        let fv = schlick_weight(n_dot_v);
+        let fd90 = 0.5 + 2.0 * params.roughness * l_dot_h * l_dot_h;
+        let fd = (1.0 + (fd90 - 1.0) * fl) * (1.0 + (fd90 - 1.0) * fv);
+        let fh = schlick_weight(l_dot_h);
+        let diffuse = (fd / PI) * params.base_color + fh * params.sheen_color;
+
+        // GGX specular.
+        let white = Color::new(1.0, 1.0, 1.0);
+        let fresnel = params.specular_f0 + fh * (white - params.specular_f0);
+        let specular = (ggx_d(n_dot_h, params.alpha)
+            * smith_g1(n_dot_l, params.alpha)
+            * smith_g1(n_dot_v, params.alpha)
+            / (4.0 * n_dot_l * n_dot_v))
+            * fresnel;
+
+        // GGX clearcoat with a fixed index of refraction of 1.5.
+        let clearcoat_fresnel = 0.04 + 0.96 * fh;
+        let clearcoat = 0.25
+            * params.clearcoat
+            * clearcoat_fresnel
+            * ggx_d(n_dot_h, params.clearcoat_alpha)
+            * smith_g1(n_dot_l, params.clearcoat_alpha)
+            * smith_g1(n_dot_v, params.clearcoat_alpha)
+            / (4.0 * n_dot_l * n_dot_v);
+
+        (params.diffuse_weight() * diffuse + specular + clearcoat * white) * n_dot_l
+    }
+
+    /// Computes the probability density of sampling `wi` from the reflective lobes.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The shading parameters at the hit point
+    /// * `n` - The shading normal, facing the viewer
+    /// * `wo` - The unit direction towards the viewer
+    /// * `wi` - The unit direction towards the light
+    ///
+    /// # Returns
+    ///
+    /// The solid angle density of `wi`, or 0.0 below the surface
+    fn pdf_reflection(params: &ShadingParams, n: &Vec3, wo: &Vec3, wi: &Vec3) -> f64 {
+        let n_dot_l = n.dot(wi);
+        if n_dot_l <= 0.0 || n.dot(wo) <= 0.0 {
+            return 0.0;
+        }
+
+        let h = (*wi + *wo).unit_vector();
+        let n_dot_h = n.dot(&h);
+        let o_dot_h = f64::abs(wo.dot(&h));
+
+        let (p_diffuse, p_specular, p_clearcoat) = params.lobe_probabilities();
+
+        p_diffuse * n_dot_l / PI
+            + p_specular * ggx_d(n_dot_h, params.alpha) * n_dot_h / (4.0 * o_dot_h)
+            + p_clearcoat * ggx_d(n_dot_h, params.clearcoat_alpha) * n_dot_h / (4.0 * o_dot_h)
+    }
+
+    /// Samples the smooth transmission lobe.
+    ///
+    /// The ray is either reflected or refracted with probability given by
+    /// Schlick's Fresnel approximation. Refracted light is tinted by the
+    /// base color; reflected light is not. Both directions are delta lobes.
+    fn scatter_transmission(
+        &self,
+        params: &ShadingParams,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
+    ) -> Scatter {
+        let ri = match rec.front_face {
+            true => 1.0 / self.ior,
+            false => self.ior,
+        };
+
+        let unit_direction = r_in.direction().unit_vector();
+        let cos_theta = f64::min(-unit_direction.dot(&rec.normal), 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let cannot_refract = ri * sin_theta > 1.0;
+
+        let mut r0 = (1.0 - ri) / (1.0 + ri);
+        r0 = r0 * r0;
+        let reflectance = r0 + (1.0 - r0) * schlick_weight(cos_theta);
+
+        match cannot_refract || reflectance > sampler.get_1d() {
+            true => {
+                *attenuation = Color::new(1.0, 1.0, 1.0);
+                *scattered = Ray::with_wavelength(
+                    rec.p,
+                    Vec3::reflect(&unit_direction, &rec.normal),
+                    r_in.wavelength(),
+                );
+            }
+            false => {
+                *attenuation = params.base_color;
+                *scattered = Ray::with_wavelength(
+                    rec.p,
+                    Vec3::refract(&unit_direction, &rec.normal, ri),
+                    r_in.wavelength(),
+                );
+            }
+        }
+
+        Scatter::Specular
+    }
+}
+
+impl Material for Principled {
+    /// Scatters the ray by stochastically choosing one of the lobes.
+    ///
+    /// The transmission lobe is chosen with probability equal to its weight.
+    /// Otherwise a direction is sampled from one of the reflective lobes, and
+    /// the attenuation is the full reflective BSDF divided by the combined
+    /// density of all reflective lobes and by the probability of not choosing
+    /// transmission. The reflective lobes already carry their share of the
+    /// dielectric base through `diffuse_weight`, so they are not scaled down
+    /// a second time.
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
+    ) -> Scatter {
+        let params = self.shading_params(rec);
+
+        if sampler.get_1d() < params.transmission_weight() {
+            return self.scatter_transmission(&params, r_in, rec, attenuation, scattered, sampler);
+        }
+
+        let wo = -r_in.direction().unit_vector();
+        let n = rec.normal;
+        let (p_diffuse, p_specular, _) = params.lobe_probabilities();
+
+        let choice = sampler.get_1d();
+        let wi = if choice < p_diffuse {
+            let direction = n + Vec3::random_unit_vector(sampler);
+            match direction.near_zero() {
+                true => n,
+                false => direction.unit_vector(),
+            }
+        } else {
+            let alpha = match choice < p_diffuse + p_specular {
+                true => params.alpha,
+                false => params.clearcoat_alpha,
+            };
+            let h = sample_ggx_half_vector(&n, alpha, sampler);
+            Vec3::reflect(&-wo, &h)
+        };
+
+        let pdf =
+            (1.0 - params.transmission_weight()) * Self::pdf_reflection(&params, &n, &wo, &wi);
+        if pdf <= 0.0 {
+            return Scatter::Absorbed;
+        }
+
+        *attenuation = Self::eval_reflection(&params, &n, &wo, &wi) / pdf;
+        *scattered = Ray::with_wavelength(rec.p, wi, r_in.wavelength());
+        Scatter::Scattered
+    }
+
+    /// Evaluates the reflective lobes; the smooth transmission lobe has no density.
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let params = self.shading_params(rec);
+        let wo = -r_in.direction().unit_vector();
+        let wi = scattered.direction().unit_vector();
+        Self::eval_reflection(&params, &rec.normal, &wo, &wi)
+    }
+
+    /// Returns the density of sampling a reflective lobe in the scattered direction.
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let params = self.shading_params(rec);
+        let wo = -r_in.direction().unit_vector();
+        let wi = scattered.direction().unit_vector();
+        (1.0 - params.transmission_weight()) * Self::pdf_reflection(&params, &rec.normal, &wo, &wi)
+    }
+}
+
+/// Linearly interpolates between two colors.
+fn lerp(a: Color, b: Color, t: f64) -> Color {
+    (1.0 - t) * a + t * b
+}
+
+/// Returns the Schlick Fresnel weight (1 - cos)^5.
+fn schlick_weight(cosine: f64) -> f64 {
+    f64::powi((1.0 - cosine).clamp(0.0, 1.0), 5)
+}
+
+/// Evaluates the GGX (Trowbridge-Reitz) normal distribution function.
+///
+/// # Arguments
+///
+/// * `n_dot_h` - Cosine of the angle between the normal and the half vector
+/// * `alpha` - The GGX roughness
+fn ggx_d(n_dot_h: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    let t = 1.0 + (a2 - 1.0) * n_dot_h * n_dot_h;
+    a2 / (PI * t * t)
+}
+
+/// Evaluates the Smith masking function for the GGX distribution.
+///
+/// # Arguments
+///
+/// * `n_dot_v` - Cosine of the angle between the normal and the direction
+/// * `alpha` - The GGX roughness
+fn smith_g1(n_dot_v: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    2.0 * n_dot_v / (n_dot_v + (a2 + (1.0 - a2) * n_dot_v * n_dot_v).sqrt())
+}
+
+/// Samples a microfacet normal proportionally to D(h) cos(theta_h).
+///
+/// # Arguments
+///
+/// * `n` - The surface normal
+/// * `alpha` - The GGX roughness
+/// * `sampler` - The source of random numbers
+///
+/// # Returns
+///
+/// A unit half vector in world space
+fn sample_ggx_half_vector(n: &Vec3, alpha: f64, sampler: &mut dyn Sampler) -> Vec3 {
+    let (r1, r2) = sampler.get_2d();
+
+    let tan2_theta = alpha * alpha * r1 / (1.0 - r1);
+    let cos_theta = 1.0 / (1.0 + tan2_theta).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * r2;
+
+    let uvw = Onb::new(n);
+    uvw.transform(&Vec3::new(
+        sin_theta * phi.cos(),
+        sin_theta * phi.sin(),
+        cos_theta,
+    ))
+}
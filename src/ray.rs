@@ -13,6 +13,7 @@ use crate::vec3::Vec3;
 /// A ray is defined by:
 /// - An origin point where the ray starts
 /// - A direction vector indicating the ray's direction
+/// - An optional wavelength, when tracing individual wavelengths of light
 ///
 /// The ray can be parameterized by a distance t, where any point on the ray
 /// can be expressed as: origin + direction * t
@@ -22,6 +23,8 @@ pub struct Ray {
     origin: Point3,
     /// The direction vector of the ray (should be normalized)
     direction: Vec3,
+    /// The wavelength carried by the ray in nanometers, if any
+    wavelength: Option<f64>,
 }
 
 impl Ray {
@@ -32,7 +35,29 @@ impl Ray {
     /// * `origin` - The starting point of the ray
     /// * `direction` - The direction vector of the ray
     pub fn new(origin: Point3, direction: Vec3) -> Self {
-        Self { origin, direction }
+        Self {
+            origin,
+            direction,
+            wavelength: None,
+        }
+    }
+
+    /// Creates a new ray that carries a wavelength.
+    ///
+    /// Materials use this to keep the wavelength of the incoming ray on the
+    /// scattered ray.
+    ///
+    /// # Arguments
+    ///
+    /// * `origin` - The starting point of the ray
+    /// * `direction` - The direction vector of the ray
+    /// * `wavelength` - The wavelength in nanometers, or `None` for RGB rays
+    pub fn with_wavelength(origin: Point3, direction: Vec3, wavelength: Option<f64>) -> Self {
+        Self {
+            origin,
+            direction,
+            wavelength,
+        }
     }
 
     /// Returns the point at distance t along the ray.
@@ -57,4 +82,9 @@ impl Ray {
     pub fn direction(&self) -> Vec3 {
         self.direction
     }
+
+    /// Returns the wavelength of the ray in nanometers, if it carries one
+    pub fn wavelength(&self) -> Option<f64> {
+        self.wavelength
+    }
 }
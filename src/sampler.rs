@@ -0,0 +1,580 @@
+//! Sample generation for the raytracer.
+//!
+//! Every random decision made while rendering draws its numbers from a
+//! `Sampler`, which is passed explicitly from the camera through the
+//! integrators, hittables and materials. The camera starts the sampler at
+//! every camera sample of every pixel, so a sampler can distribute the
+//! numbers of a pixel's samples evenly instead of independently. This
+//! module provides:
+//! - `IndependentSampler`: Uniform random numbers (the default)
+//! - `StratifiedSampler`: Jittered stratification of every dimension
+//! - `HaltonSampler`: The Halton sequence, Owen-scrambled per pixel
+//! - `SobolSampler`: Owen-scrambled Sobol points, padded across dimensions
+//! - `Pcg32`: A small seedable random number generator
+//!
+//! All samplers are seeded, and the values of a pixel sample depend only on
+//! the seed, the pixel and the sample index, so rendering with the same seed
+//! reproduces the same numbers whatever the order pixels are sampled in.
+
+/// A source of sample values in \[0,1).
+///
+/// Values are drawn in dimensions: the first pair of a camera sample places
+/// it in the pixel, and every following call moves on to the next
+/// dimension. The same sequence of calls for the same pixel sample yields
+/// the same values.
+pub trait Sampler: Send + Sync {
+    /// Starts a new camera sample.
+    ///
+    /// # Arguments
+    ///
+    /// * `i` - The horizontal coordinate of the pixel
+    /// * `j` - The vertical coordinate of the pixel
+    /// * `sample_index` - The index of the sample within the pixel
+    fn start_pixel_sample(&mut self, i: u32, j: u32, sample_index: u32);
+
+    /// Returns the value of the next dimension.
+    fn get_1d(&mut self) -> f64;
+
+    /// Returns the values of the next two dimensions, which are sampled
+    /// jointly.
+    fn get_2d(&mut self) -> (f64, f64);
+
+    /// Returns the position of the camera sample within its pixel.
+    ///
+    /// The camera calls this first for every camera sample.
+    fn get_pixel_2d(&mut self) -> (f64, f64) {
+        self.get_2d()
+    }
+
+    /// Returns a copy of the sampler that derives its values from another seed.
+    ///
+    /// The camera makes a copy seeded with its own seed for every pixel, so
+    /// pixels can be sampled in parallel and a render is determined by the
+    /// camera's seed alone.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - The seed of the copy
+    fn clone_with_seed(&self, seed: u64) -> Box<dyn Sampler>;
+
+    /// Describes the sampler and the settings that change its samples.
+    ///
+    /// The seed is left out, since the camera reseeds every copy it makes.
+    /// Checkpoints record the description, so a render is only resumed with
+    /// the sampler it was started with.
+    fn description(&self) -> String;
+}
+
+/// A sampler that draws independent uniform random numbers.
+///
+/// This is the plain Monte Carlo baseline: samples of a pixel may clump
+/// together, but every dimension is equally good.
+#[derive(Debug, Clone)]
+pub struct IndependentSampler {
+    /// The seed all pixel samples are derived from
+    pub seed: u64,
+    /// The generator for the current pixel sample
+    rng: Pcg32,
+}
+
+impl IndependentSampler {
+    /// Creates a new independent sampler.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - The seed all pixel samples are derived from
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: Pcg32::new(seed, 0),
+        }
+    }
+}
+
+impl Default for IndependentSampler {
+    /// Creates an independent sampler with seed 0.
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, i: u32, j: u32, sample_index: u32) {
+        self.rng = Pcg32::new(hash(&[self.seed, i as u64, j as u64]), sample_index as u64);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.rng.next_f64()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.rng.next_f64(), self.rng.next_f64())
+    }
+
+    fn clone_with_seed(&self, seed: u64) -> Box<dyn Sampler> {
+        Box::new(Self {
+            seed,
+            ..self.clone()
+        })
+    }
+
+    fn description(&self) -> String {
+        "independent".to_string()
+    }
+}
+
+/// A sampler that stratifies every dimension of a pixel's samples.
+///
+/// Each pixel is divided into `x_samples` by `y_samples` strata and every
+/// camera sample falls in a different one. Every other 1D and 2D dimension
+/// is stratified the same way, with the strata assigned to samples in a
+/// different random order per dimension so the dimensions are not
+/// correlated. Set the camera's `samples_per_pixel` to
+/// `x_samples * y_samples`; further samples start over with the strata.
+#[derive(Debug, Clone)]
+pub struct StratifiedSampler {
+    /// The number of strata across the pixel
+    pub x_samples: u32,
+    /// The number of strata down the pixel
+    pub y_samples: u32,
+    /// Whether samples are placed randomly within their stratum, rather
+    /// than at its center
+    pub jitter: bool,
+    /// The seed all pixel samples are derived from
+    pub seed: u64,
+    /// The pixel being sampled
+    pixel: (u32, u32),
+    /// The index of the current sample within the pixel
+    sample_index: u32,
+    /// The next dimension
+    dimension: u32,
+    /// The generator for the jitter of the current pixel sample
+    rng: Pcg32,
+}
+
+impl StratifiedSampler {
+    /// Creates a new stratified sampler.
+    ///
+    /// # Arguments
+    ///
+    /// * `x_samples` - The number of strata across the pixel
+    /// * `y_samples` - The number of strata down the pixel
+    /// * `jitter` - Whether samples are placed randomly within their stratum
+    /// * `seed` - The seed all pixel samples are derived from
+    pub fn new(x_samples: u32, y_samples: u32, jitter: bool, seed: u64) -> Self {
+        Self {
+            x_samples: x_samples.max(1),
+            y_samples: y_samples.max(1),
+            jitter,
+            seed,
+            pixel: (0, 0),
+            sample_index: 0,
+            dimension: 0,
+            rng: Pcg32::new(seed, 0),
+        }
+    }
+
+    /// Returns the offset of a sample within its stratum.
+    fn offset(&mut self) -> f64 {
+        match self.jitter {
+            true => self.rng.next_f64(),
+            false => 0.5,
+        }
+    }
+
+    /// Returns the stratum of the current sample in the next dimension.
+    fn stratum(&mut self, count: u32) -> u32 {
+        let (i, j) = self.pixel;
+        let permutation = hash(&[self.seed, i as u64, j as u64, self.dimension as u64]);
+        self.dimension += 1;
+        permutation_element(self.sample_index % count, count, permutation as u32)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, i: u32, j: u32, sample_index: u32) {
+        self.pixel = (i, j);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+        self.rng = Pcg32::new(hash(&[self.seed, i as u64, j as u64]), sample_index as u64);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let count = self.x_samples * self.y_samples;
+        let stratum = self.stratum(count);
+        (stratum as f64 + self.offset()) / count as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let stratum = self.stratum(self.x_samples * self.y_samples);
+        let x = stratum % self.x_samples;
+        let y = stratum / self.x_samples;
+        (
+            (x as f64 + self.offset()) / self.x_samples as f64,
+            (y as f64 + self.offset()) / self.y_samples as f64,
+        )
+    }
+
+    fn clone_with_seed(&self, seed: u64) -> Box<dyn Sampler> {
+        Box::new(Self {
+            seed,
+            ..self.clone()
+        })
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "stratified {}x{}, jitter {}",
+            self.x_samples, self.y_samples, self.jitter
+        )
+    }
+}
+
+/// The number of dimensions drawn from the Halton sequence.
+const HALTON_DIMENSIONS: usize = 256;
+
+/// The first primes, one base per Halton dimension.
+const PRIMES: [u64; HALTON_DIMENSIONS] = primes();
+
+/// A sampler that draws the samples of each pixel from the Halton sequence.
+///
+/// Dimension `d` of sample `n` is the radical inverse of `n` in the `d`th
+/// prime base, which spreads any number of samples evenly. The digits are
+/// Owen-scrambled with a different seed per pixel and dimension, which
+/// breaks up the correlation between the higher bases and keeps
+/// neighboring pixels from repeating the same pattern. Dimensions past the
+/// first 256 are drawn independently.
+#[derive(Debug, Clone)]
+pub struct HaltonSampler {
+    /// The seed of the per-pixel scrambles
+    pub seed: u64,
+    /// The pixel being sampled
+    pixel: (u32, u32),
+    /// The index of the current sample within the pixel
+    sample_index: u32,
+    /// The next dimension
+    dimension: u32,
+}
+
+impl HaltonSampler {
+    /// Creates a new Halton sampler.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - The seed of the per-pixel scrambles
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel: (0, 0),
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Default for HaltonSampler {
+    /// Creates a Halton sampler with seed 0.
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, i: u32, j: u32, sample_index: u32) {
+        self.pixel = (i, j);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (i, j) = self.pixel;
+        let dimension = self.dimension as usize;
+        self.dimension += 1;
+
+        let hash = hash(&[self.seed, i as u64, j as u64, dimension as u64]);
+        match PRIMES.get(dimension) {
+            Some(&base) => scrambled_radical_inverse(base, self.sample_index as u64, hash),
+            None => to_unit(mix_bits(hash ^ self.sample_index as u64)),
+        }
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+
+    fn clone_with_seed(&self, seed: u64) -> Box<dyn Sampler> {
+        Box::new(Self {
+            seed,
+            ..self.clone()
+        })
+    }
+
+    fn description(&self) -> String {
+        "halton".to_string()
+    }
+}
+
+/// A sampler that draws Owen-scrambled Sobol points.
+///
+/// Every pair of dimensions takes the first two dimensions of the Sobol
+/// sequence, which are well stratified together for power-of-two sample
+/// counts. The points are scrambled with a hash-based Owen scramble and
+/// the order in which they are used is shuffled, both with a different
+/// seed per pixel and dimension pair, so the pairs are independent of
+/// each other and of other pixels (Burley 2020). It works best with a
+/// power-of-two `samples_per_pixel`.
+#[derive(Debug, Clone)]
+pub struct SobolSampler {
+    /// The seed of the scrambles and shuffles
+    pub seed: u64,
+    /// The pixel being sampled
+    pixel: (u32, u32),
+    /// The index of the current sample within the pixel
+    sample_index: u32,
+    /// The next dimension
+    dimension: u32,
+}
+
+impl SobolSampler {
+    /// Creates a new Sobol sampler.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - The seed of the scrambles and shuffles
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel: (0, 0),
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// Returns the seed of the next dimension and moves past it.
+    fn dimension_seed(&mut self, dimensions: u32) -> u32 {
+        let (i, j) = self.pixel;
+        let seed = hash(&[self.seed, i as u64, j as u64, self.dimension as u64]);
+        self.dimension += dimensions;
+        seed as u32
+    }
+}
+
+impl Default for SobolSampler {
+    /// Creates a Sobol sampler with seed 0.
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, i: u32, j: u32, sample_index: u32) {
+        self.pixel = (i, j);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let seed = self.dimension_seed(1);
+        let index = nested_uniform_scramble(self.sample_index, seed);
+        let x = nested_uniform_scramble(sobol(index, 0), mix_bits(seed as u64 + 1) as u32);
+        x as f64 / (1u64 << 32) as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let seed = self.dimension_seed(2);
+        let index = nested_uniform_scramble(self.sample_index, seed);
+        let x = nested_uniform_scramble(sobol(index, 0), mix_bits(seed as u64 + 1) as u32);
+        let y = nested_uniform_scramble(sobol(index, 1), mix_bits(seed as u64 + 2) as u32);
+        (
+            x as f64 / (1u64 << 32) as f64,
+            y as f64 / (1u64 << 32) as f64,
+        )
+    }
+
+    fn clone_with_seed(&self, seed: u64) -> Box<dyn Sampler> {
+        Box::new(Self {
+            seed,
+            ..self.clone()
+        })
+    }
+
+    fn description(&self) -> String {
+        "sobol".to_string()
+    }
+}
+
+/// A PCG32 pseudo-random number generator (O'Neill's PCG-XSH-RR).
+///
+/// It is small, fast and statistically strong, and different streams with
+/// the same seed produce independent sequences.
+#[derive(Debug, Clone)]
+pub struct Pcg32 {
+    /// The internal state
+    state: u64,
+    /// The stream increment; always odd
+    inc: u64,
+}
+
+impl Pcg32 {
+    /// Creates a new generator.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - The starting seed
+    /// * `stream` - The stream to draw from; generators with different
+    ///   streams produce independent sequences
+    pub fn new(seed: u64, stream: u64) -> Self {
+        let mut rng = Self {
+            state: 0,
+            inc: (stream << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    /// Returns the next 32 random bits
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(6364136223846793005).wrapping_add(self.inc);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    /// Returns a random value in \[0,1) with 53 bits of precision
+    pub fn next_f64(&mut self) -> f64 {
+        let bits = ((self.next_u32() as u64) << 32) | self.next_u32() as u64;
+        to_unit(bits)
+    }
+}
+
+/// Scrambles the bits of a value with the SplitMix64 finalizer.
+pub(crate) fn mix_bits(mut v: u64) -> u64 {
+    v = v.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    v = (v ^ (v >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    v = (v ^ (v >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    v ^ (v >> 31)
+}
+
+/// Hashes a sequence of values into well-mixed bits.
+pub(crate) fn hash(values: &[u64]) -> u64 {
+    values
+        .iter()
+        .fold(0, |h, &v| mix_bits(h.rotate_left(5) ^ v))
+}
+
+/// Maps 64 random bits to a value in \[0,1) with 53 bits of precision.
+fn to_unit(bits: u64) -> f64 {
+    (bits >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+}
+
+/// Returns the Owen-scrambled radical inverse of an index.
+///
+/// The radical inverse mirrors the digits of the index in the given base
+/// around the decimal point. Each digit is then permuted randomly, with a
+/// permutation that depends on the digits before it, down to the
+/// precision of an f64 (so the zero digits past the end of the index are
+/// scrambled too).
+fn scrambled_radical_inverse(base: u64, mut index: u64, seed: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut reversed_digits: u64 = 0;
+    let mut inv_base_power = 1.0;
+    while 1.0 - inv_base_power < 1.0 {
+        let digit = index % base;
+        let permutation = mix_bits(seed ^ reversed_digits) as u32;
+        let digit = permutation_element(digit as u32, base as u32, permutation) as u64;
+        reversed_digits = reversed_digits * base + digit;
+        inv_base_power *= inv_base;
+        index /= base;
+    }
+    (reversed_digits as f64 * inv_base_power).min(1.0 - f64::EPSILON / 2.0)
+}
+
+/// Returns the element at position `i` of a random permutation of
+/// `0..count`, without building the permutation (Kensler 2013).
+fn permutation_element(mut i: u32, count: u32, seed: u32) -> u32 {
+    let mut w = count - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < count {
+            return ((i as u64 + seed as u64) % count as u64) as u32;
+        }
+    }
+}
+
+/// Returns one of the first two dimensions of the Sobol sequence as 32 bits.
+fn sobol(index: u32, dimension: u32) -> u32 {
+    let mut result = 0;
+    let mut direction = 1u32 << 31;
+    for bit in 0..32 {
+        if (index >> bit) & 1 != 0 {
+            result ^= direction;
+        }
+        direction = match dimension {
+            // The van der Corput sequence.
+            0 => direction >> 1,
+            // Generated by the primitive polynomial x + 1.
+            _ => direction ^ (direction >> 1),
+        };
+    }
+    result
+}
+
+/// Applies a hash-based Owen scramble to the bits of a value (Burley 2020).
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x.reverse_bits()
+}
+
+/// Computes the first primes at compile time.
+const fn primes() -> [u64; HALTON_DIMENSIONS] {
+    let mut primes = [0; HALTON_DIMENSIONS];
+    let mut count = 0;
+    let mut candidate = 2;
+    while count < HALTON_DIMENSIONS {
+        let mut is_prime = true;
+        let mut k = 0;
+        while k < count && primes[k] * primes[k] <= candidate {
+            if candidate % primes[k] == 0 {
+                is_prime = false;
+                break;
+            }
+            k += 1;
+        }
+        if is_prime {
+            primes[count] = candidate;
+            count += 1;
+        }
+        candidate += 1;
+    }
+    primes
+}
@@ -0,0 +1,446 @@
+//! Serializable scene descriptions for the raytracer.
+//!
+//! Scenes are built from trait objects, which cannot be sent to another
+//! process as they are. This module provides plain descriptions of scenes
+//! that can be written to a byte stream, read back and built into the same
+//! scene anywhere:
+//! - `SceneDescription`: A list of spheres and spherical volumes
+//! - `SphereDescription`: A sphere and its material
+//! - `MaterialDescription`: The materials a described sphere can have
+//! - `MediumDescription`: A sphere filled with a constant-density medium
+//!
+//! Only spheres, constant-density media bounded by spheres and materials
+//! with constant parameters can be described. Textures, grid volumes and
+//! custom `Hittable` or `Material` implementations cannot be sent to
+//! another process, so scenes using them must be rendered locally.
+
+use std::{
+    io::{self, Read, Write},
+    sync::Arc,
+};
+
+use crate::{
+    constant_medium::ConstantMedium,
+    film::{read_f64, read_u32, read_vec3, write_vec3},
+    hittable_list::HittableList,
+    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal, OrenNayar, RefractiveIndex},
+    principled::Principled,
+    sphere::Sphere,
+    subsurface::Subsurface,
+    texture::SolidColor,
+    vec3::{Color, Point3},
+};
+
+/// The material of a described sphere.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MaterialDescription {
+    /// A `Lambertian` material with the given albedo
+    Lambertian(Color),
+    /// A `Metal` material with the given albedo and fuzz
+    Metal(Color, f64),
+    /// A `Dielectric` material with the given index of refraction
+    Dielectric(f64),
+    /// A `DiffuseLight` with the given emitted color
+    DiffuseLight(Color),
+    /// An `OrenNayar` material with the given albedo and roughness in degrees
+    OrenNayar(Color, f64),
+    /// A `Dielectric` material with a wavelength-dependent index of refraction
+    DispersiveDielectric(RefractiveIndex),
+    /// A `Subsurface` material
+    Subsurface {
+        /// The refractive index of the boundary
+        refraction_index: f64,
+        /// The scattering coefficient per unit distance
+        scattering: Color,
+        /// The absorption coefficient per unit distance
+        absorption: Color,
+        /// The Henyey-Greenstein asymmetry parameter of interior scattering
+        anisotropy: f64,
+    },
+    /// A `Principled` material with a constant value for every parameter
+    Principled {
+        /// The base color of the surface
+        base_color: Color,
+        /// Blend between a dielectric (0.0) and a metallic (1.0) surface
+        metallic: f64,
+        /// Perceptual roughness of the specular and diffuse lobes
+        roughness: f64,
+        /// Dielectric specular amount
+        specular: f64,
+        /// Strength of the clearcoat layer
+        clearcoat: f64,
+        /// Perceptual roughness of the clearcoat layer
+        clearcoat_roughness: f64,
+        /// Strength of the sheen lobe
+        sheen: f64,
+        /// Blend of the sheen color from white to the base color hue
+        sheen_tint: f64,
+        /// Fraction of the dielectric base that transmits light
+        transmission: f64,
+        /// Index of refraction used by the transmission lobe
+        ior: f64,
+    },
+}
+
+impl MaterialDescription {
+    /// Builds the described material.
+    pub fn build(&self) -> Arc<dyn Material> {
+        match *self {
+            MaterialDescription::Lambertian(albedo) => Arc::new(Lambertian::new(albedo)),
+            MaterialDescription::Metal(albedo, fuzz) => Arc::new(Metal::new(albedo, fuzz)),
+            MaterialDescription::Dielectric(refraction_index) => {
+                Arc::new(Dielectric::new(refraction_index))
+            }
+            MaterialDescription::DiffuseLight(emit) => Arc::new(DiffuseLight::new(emit)),
+            MaterialDescription::OrenNayar(albedo, sigma) => {
+                Arc::new(OrenNayar::new(albedo, sigma))
+            }
+            MaterialDescription::DispersiveDielectric(refraction_index) => {
+                Arc::new(Dielectric::dispersive(refraction_index))
+            }
+            MaterialDescription::Subsurface {
+                refraction_index,
+                scattering,
+                absorption,
+                anisotropy,
+            } => Arc::new(
+                Subsurface::new(refraction_index, scattering, absorption)
+                    .with_anisotropy(anisotropy),
+            ),
+            MaterialDescription::Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                clearcoat,
+                clearcoat_roughness,
+                sheen,
+                sheen_tint,
+                transmission,
+                ior,
+            } => Arc::new(Principled {
+                base_color: Arc::new(SolidColor::new(base_color)),
+                metallic: Arc::new(SolidColor::gray(metallic)),
+                roughness: Arc::new(SolidColor::gray(roughness)),
+                specular: Arc::new(SolidColor::gray(specular)),
+                clearcoat: Arc::new(SolidColor::gray(clearcoat)),
+                clearcoat_roughness: Arc::new(SolidColor::gray(clearcoat_roughness)),
+                sheen: Arc::new(SolidColor::gray(sheen)),
+                sheen_tint: Arc::new(SolidColor::gray(sheen_tint)),
+                transmission: Arc::new(SolidColor::gray(transmission)),
+                ior,
+            }),
+        }
+    }
+
+    /// Writes the description to a byte stream.
+    fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        match *self {
+            MaterialDescription::Lambertian(albedo) => {
+                out.write_all(&[0])?;
+                write_vec3(out, &albedo)
+            }
+            MaterialDescription::Metal(albedo, fuzz) => {
+                out.write_all(&[1])?;
+                write_vec3(out, &albedo)?;
+                out.write_all(&fuzz.to_le_bytes())
+            }
+            MaterialDescription::Dielectric(refraction_index) => {
+                out.write_all(&[2])?;
+                out.write_all(&refraction_index.to_le_bytes())
+            }
+            MaterialDescription::DiffuseLight(emit) => {
+                out.write_all(&[3])?;
+                write_vec3(out, &emit)
+            }
+            MaterialDescription::OrenNayar(albedo, sigma) => {
+                out.write_all(&[4])?;
+                write_vec3(out, &albedo)?;
+                out.write_all(&sigma.to_le_bytes())
+            }
+            MaterialDescription::DispersiveDielectric(refraction_index) => {
+                out.write_all(&[5])?;
+                write_refractive_index(out, &refraction_index)
+            }
+            MaterialDescription::Subsurface {
+                refraction_index,
+                scattering,
+                absorption,
+                anisotropy,
+            } => {
+                out.write_all(&[6])?;
+                out.write_all(&refraction_index.to_le_bytes())?;
+                write_vec3(out, &scattering)?;
+                write_vec3(out, &absorption)?;
+                out.write_all(&anisotropy.to_le_bytes())
+            }
+            MaterialDescription::Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                clearcoat,
+                clearcoat_roughness,
+                sheen,
+                sheen_tint,
+                transmission,
+                ior,
+            } => {
+                out.write_all(&[7])?;
+                write_vec3(out, &base_color)?;
+                for value in [
+                    metallic,
+                    roughness,
+                    specular,
+                    clearcoat,
+                    clearcoat_roughness,
+                    sheen,
+                    sheen_tint,
+                    transmission,
+                    ior,
+                ] {
+                    out.write_all(&value.to_le_bytes())?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Reads a description written by `write_to` from a byte stream.
+    fn read_from<R: Read>(input: &mut R) -> io::Result<Self> {
+        let mut kind = [0];
+        input.read_exact(&mut kind)?;
+        match kind[0] {
+            0 => Ok(MaterialDescription::Lambertian(read_vec3(input)?)),
+            1 => Ok(MaterialDescription::Metal(
+                read_vec3(input)?,
+                read_f64(input)?,
+            )),
+            2 => Ok(MaterialDescription::Dielectric(read_f64(input)?)),
+            3 => Ok(MaterialDescription::DiffuseLight(read_vec3(input)?)),
+            4 => Ok(MaterialDescription::OrenNayar(
+                read_vec3(input)?,
+                read_f64(input)?,
+            )),
+            5 => Ok(MaterialDescription::DispersiveDielectric(
+                read_refractive_index(input)?,
+            )),
+            6 => Ok(MaterialDescription::Subsurface {
+                refraction_index: read_f64(input)?,
+                scattering: read_vec3(input)?,
+                absorption: read_vec3(input)?,
+                anisotropy: read_f64(input)?,
+            }),
+            7 => Ok(MaterialDescription::Principled {
+                base_color: read_vec3(input)?,
+                metallic: read_f64(input)?,
+                roughness: read_f64(input)?,
+                specular: read_f64(input)?,
+                clearcoat: read_f64(input)?,
+                clearcoat_roughness: read_f64(input)?,
+                sheen: read_f64(input)?,
+                sheen_tint: read_f64(input)?,
+                transmission: read_f64(input)?,
+                ior: read_f64(input)?,
+            }),
+            kind => Err(invalid_data(format!("unknown material kind {}", kind))),
+        }
+    }
+}
+
+/// Writes a refractive index to a byte stream.
+fn write_refractive_index<W: Write>(out: &mut W, index: &RefractiveIndex) -> io::Result<()> {
+    let (kind, values) = match *index {
+        RefractiveIndex::Constant(n) => (0, vec![n]),
+        RefractiveIndex::Cauchy { a, b } => (1, vec![a, b]),
+        RefractiveIndex::Sellmeier { b, c } => (2, [b, c].concat()),
+    };
+    out.write_all(&[kind])?;
+    for value in values {
+        out.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Reads a refractive index written by `write_refractive_index` from a byte stream.
+fn read_refractive_index<R: Read>(input: &mut R) -> io::Result<RefractiveIndex> {
+    let mut kind = [0];
+    input.read_exact(&mut kind)?;
+    match kind[0] {
+        0 => Ok(RefractiveIndex::Constant(read_f64(input)?)),
+        1 => Ok(RefractiveIndex::Cauchy {
+            a: read_f64(input)?,
+            b: read_f64(input)?,
+        }),
+        2 => {
+            let mut read_three = || -> io::Result<[f64; 3]> {
+                Ok([read_f64(input)?, read_f64(input)?, read_f64(input)?])
+            };
+            let b = read_three()?;
+            let c = read_three()?;
+            Ok(RefractiveIndex::Sellmeier { b, c })
+        }
+        kind => Err(invalid_data(format!(
+            "unknown refractive index kind {}",
+            kind
+        ))),
+    }
+}
+
+/// Creates an error for invalid data read from a byte stream.
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// A described sphere.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SphereDescription {
+    /// The center of the sphere
+    pub center: Point3,
+    /// The radius of the sphere
+    pub radius: f64,
+    /// The material of the sphere
+    pub material: MaterialDescription,
+}
+
+/// A described sphere filled with a constant-density medium.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MediumDescription {
+    /// The center of the bounding sphere
+    pub center: Point3,
+    /// The radius of the bounding sphere
+    pub radius: f64,
+    /// The scattering density of the medium
+    pub density: f64,
+    /// The color of the light scattered by the medium
+    pub albedo: Color,
+}
+
+/// A scene made of spheres and spherical volumes, which can be sent to other processes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SceneDescription {
+    /// The spheres of the scene
+    pub spheres: Vec<SphereDescription>,
+    /// The constant-density media of the scene
+    pub media: Vec<MediumDescription>,
+}
+
+impl SceneDescription {
+    /// Creates an empty scene description.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a sphere to the scene.
+    ///
+    /// # Arguments
+    ///
+    /// * `center` - The center of the sphere
+    /// * `radius` - The radius of the sphere
+    /// * `material` - The material of the sphere
+    pub fn add_sphere(&mut self, center: Point3, radius: f64, material: MaterialDescription) {
+        self.spheres.push(SphereDescription {
+            center,
+            radius,
+            material,
+        });
+    }
+
+    /// Adds a sphere filled with a constant-density medium to the scene.
+    ///
+    /// # Arguments
+    ///
+    /// * `center` - The center of the bounding sphere
+    /// * `radius` - The radius of the bounding sphere
+    /// * `density` - The scattering density of the medium
+    /// * `albedo` - The color of the light scattered by the medium
+    pub fn add_medium(&mut self, center: Point3, radius: f64, density: f64, albedo: Color) {
+        self.media.push(MediumDescription {
+            center,
+            radius,
+            density,
+            albedo,
+        });
+    }
+
+    /// Builds the described scene.
+    pub fn build(&self) -> HittableList {
+        let mut world = HittableList::new();
+        for sphere in &self.spheres {
+            world.add(Box::new(Sphere::new(
+                sphere.center,
+                sphere.radius,
+                sphere.material.build(),
+            )));
+        }
+        for medium in &self.media {
+            // The boundary's material is never used by the medium.
+            let boundary = Sphere::new(
+                medium.center,
+                medium.radius,
+                Arc::new(Lambertian::new(Color::default())),
+            );
+            world.add(Box::new(ConstantMedium::new(
+                Box::new(boundary),
+                medium.density,
+                medium.albedo,
+            )));
+        }
+        world
+    }
+
+    /// Writes the description to a byte stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `out` - The stream to write to
+    ///
+    /// # Returns
+    ///
+    /// An error if writing fails
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(&(self.spheres.len() as u32).to_le_bytes())?;
+        for sphere in &self.spheres {
+            write_vec3(out, &sphere.center)?;
+            out.write_all(&sphere.radius.to_le_bytes())?;
+            sphere.material.write_to(out)?;
+        }
+        out.write_all(&(self.media.len() as u32).to_le_bytes())?;
+        for medium in &self.media {
+            write_vec3(out, &medium.center)?;
+            out.write_all(&medium.radius.to_le_bytes())?;
+            out.write_all(&medium.density.to_le_bytes())?;
+            write_vec3(out, &medium.albedo)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a description written by `write_to` from a byte stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The stream to read from
+    ///
+    /// # Returns
+    ///
+    /// The description, or an error if reading fails or the data is invalid
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<Self> {
+        let count = read_u32(input)?;
+        let mut scene = Self::new();
+        for _ in 0..count {
+            let center = read_vec3(input)?;
+            let radius = read_f64(input)?;
+            let material = MaterialDescription::read_from(input)?;
+            scene.add_sphere(center, radius, material);
+        }
+        let count = read_u32(input)?;
+        for _ in 0..count {
+            let center = read_vec3(input)?;
+            let radius = read_f64(input)?;
+            let density = read_f64(input)?;
+            let albedo = read_vec3(input)?;
+            scene.add_medium(center, radius, density, albedo);
+        }
+        Ok(scene)
+    }
+}
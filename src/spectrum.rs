@@ -0,0 +1,329 @@
+//! Wavelength and color-matching utilities for the raytracer.
+//!
+//! This module provides the pieces needed to trace individual wavelengths of
+//! light and turn them back into RGB colors:
+//! - An analytic fit of the CIE 1931 color matching functions
+//! - Conversion from CIE XYZ to linear sRGB
+//! - Uniform sampling of the visible range and per-wavelength RGB weights
+//! - Hero-wavelength sampling of several wavelengths per camera sample
+//! - Upsampling of RGB colors to smooth reflectance spectra
+
+use std::ops::{Add, AddAssign, Index, Mul};
+
+use crate::vec3::{Color, Vec3};
+
+/// Shortest wavelength traced, in nanometers
+pub const LAMBDA_MIN: f64 = 360.0;
+/// Longest wavelength traced, in nanometers
+pub const LAMBDA_MAX: f64 = 830.0;
+
+/// Number of wavelengths traced together in spectral rendering
+pub const N_SPECTRUM_SAMPLES: usize = 4;
+
+/// Linear sRGB of a uniform (equal energy) spectrum, averaged over the traced range.
+///
+/// Computed numerically from `cie_xyz` and `xyz_to_linear_srgb`. Dividing by
+/// it makes a white surface under a white sky stay white when tracing
+/// wavelengths.
+const EQUAL_ENERGY_RGB: [f64; 3] = [0.273_053_54, 0.216_050_61, 0.206_631_68];
+
+/// Transition between the blue and green bands used by `rgb_to_spectrum`, in nanometers.
+///
+/// The band edges were fitted so that the sRGB primaries survive a round
+/// trip through `rgb_to_spectrum` and `wavelength_to_rgb` to within a few
+/// percent.
+const BLUE_BAND_EDGE: (f64, f64) = (450.0, 530.0);
+/// Transition between the green and red bands used by `rgb_to_spectrum`, in nanometers.
+const RED_BAND_EDGE: (f64, f64) = (570.0, 605.0);
+
+/// Evaluates the CIE 1931 2-degree color matching functions.
+///
+/// Uses the multi-lobe piecewise Gaussian fit by Wyman, Sloan and Shirley
+/// (2013), which is accurate to well within the needs of rendering.
+///
+/// # Arguments
+///
+/// * `lambda` - The wavelength in nanometers
+///
+/// # Returns
+///
+/// The (x̄, ȳ, z̄) tristimulus values as a vector
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    let x = 1.056 * piecewise_gaussian(lambda, 599.8, 37.9, 31.0)
+        + 0.362 * piecewise_gaussian(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * piecewise_gaussian(lambda, 501.1, 20.4, 26.2);
+    let y = 0.821 * piecewise_gaussian(lambda, 568.8, 46.9, 40.5)
+        + 0.286 * piecewise_gaussian(lambda, 530.9, 16.3, 31.1);
+    let z = 1.217 * piecewise_gaussian(lambda, 437.0, 11.8, 36.0)
+        + 0.681 * piecewise_gaussian(lambda, 459.0, 26.0, 13.8);
+
+    Vec3::new(x, y, z)
+}
+
+/// Converts a CIE XYZ color to linear sRGB (D65 white point).
+///
+/// # Arguments
+///
+/// * `xyz` - The color in CIE XYZ
+///
+/// # Returns
+///
+/// The color in linear sRGB; components may be negative for colors
+/// outside the sRGB gamut
+pub fn xyz_to_linear_srgb(xyz: &Vec3) -> Color {
+    Color::new(
+        3.240_454_2 * xyz.x() - 1.537_138_5 * xyz.y() - 0.498_531_4 * xyz.z(),
+        -0.969_266_0 * xyz.x() + 1.876_010_8 * xyz.y() + 0.041_556_0 * xyz.z(),
+        0.055_643_4 * xyz.x() - 0.204_025_9 * xyz.y() + 1.057_225_2 * xyz.z(),
+    )
+}
+
+/// Maps a uniform random number to a wavelength in the traced range.
+///
+/// # Arguments
+///
+/// * `u` - A number in \[0,1)
+///
+/// # Returns
+///
+/// A wavelength in nanometers, uniformly distributed in \[LAMBDA_MIN, LAMBDA_MAX)
+pub fn sample_wavelength(u: f64) -> f64 {
+    LAMBDA_MIN + u * (LAMBDA_MAX - LAMBDA_MIN)
+}
+
+/// Returns the RGB weight of a single uniformly sampled wavelength.
+///
+/// The weight already accounts for the uniform sampling density, so
+/// averaging `radiance * wavelength_to_rgb(lambda)` over many samples gives
+/// the RGB color of the spectrum. A constant spectrum of 1.0 averages to
+/// white.
+///
+/// # Arguments
+///
+/// * `lambda` - The wavelength in nanometers
+///
+/// # Returns
+///
+/// The linear RGB weight of the wavelength
+pub fn wavelength_to_rgb(lambda: f64) -> Color {
+    let rgb = xyz_to_linear_srgb(&cie_xyz(lambda));
+    Color::new(
+        rgb.x() / EQUAL_ENERGY_RGB[0],
+        rgb.y() / EQUAL_ENERGY_RGB[1],
+        rgb.z() / EQUAL_ENERGY_RGB[2],
+    )
+}
+
+/// Evaluates a Gaussian with different widths on either side of its peak.
+///
+/// # Arguments
+///
+/// * `x` - The point to evaluate at
+/// * `mu` - The position of the peak
+/// * `sigma_low` - The width below the peak
+/// * `sigma_high` - The width above the peak
+fn piecewise_gaussian(x: f64, mu: f64, sigma_low: f64, sigma_high: f64) -> f64 {
+    let t = match x < mu {
+        true => (x - mu) / sigma_low,
+        false => (x - mu) / sigma_high,
+    };
+    f64::exp(-0.5 * t * t)
+}
+
+/// Upsamples an RGB reflectance to a smooth spectrum and evaluates it.
+///
+/// The spectrum is a blend of three smooth bands (blue, green and red) that
+/// sum to one at every wavelength, so white maps to a constant spectrum of
+/// 1.0 and reflectances in \[0,1\] stay physically plausible. Converting the
+/// spectrum back with `wavelength_to_rgb` approximately recovers the color.
+///
+/// # Arguments
+///
+/// * `rgb` - The linear RGB color to upsample
+/// * `lambda` - The wavelength in nanometers
+///
+/// # Returns
+///
+/// The value of the upsampled spectrum at `lambda`
+pub fn rgb_to_spectrum(rgb: &Color, lambda: f64) -> f64 {
+    let blue = 1.0 - smoothstep(BLUE_BAND_EDGE.0, BLUE_BAND_EDGE.1, lambda);
+    let red = smoothstep(RED_BAND_EDGE.0, RED_BAND_EDGE.1, lambda);
+    let green = 1.0 - blue - red;
+
+    rgb.x() * red + rgb.y() * green + rgb.z() * blue
+}
+
+/// A set of wavelengths traced together along a single path.
+///
+/// The first ("hero") wavelength is sampled uniformly and the others are
+/// spaced evenly across the traced range, which keeps color noise low
+/// while still letting dispersive materials fall back to the hero
+/// wavelength alone.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SampledWavelengths {
+    /// The wavelengths in nanometers
+    lambda: [f64; N_SPECTRUM_SAMPLES],
+    /// The sampling density of each wavelength (0.0 once terminated)
+    pdf: [f64; N_SPECTRUM_SAMPLES],
+}
+
+impl SampledWavelengths {
+    /// Samples a set of hero-rotated wavelengths.
+    ///
+    /// # Arguments
+    ///
+    /// * `u` - A number in \[0,1) used to place the hero wavelength
+    pub fn sample_uniform(u: f64) -> Self {
+        let mut lambda = [0.0; N_SPECTRUM_SAMPLES];
+        for (i, l) in lambda.iter_mut().enumerate() {
+            let offset = (u + i as f64 / N_SPECTRUM_SAMPLES as f64).fract();
+            *l = sample_wavelength(offset);
+        }
+
+        Self {
+            lambda,
+            pdf: [1.0 / (LAMBDA_MAX - LAMBDA_MIN); N_SPECTRUM_SAMPLES],
+        }
+    }
+
+    /// Returns the hero wavelength in nanometers
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    /// Returns true if only the hero wavelength is still being traced
+    pub fn secondary_terminated(&self) -> bool {
+        self.pdf[1..].iter().all(|p| *p == 0.0)
+    }
+
+    /// Stops tracing all wavelengths except the hero wavelength.
+    ///
+    /// This is needed when a path interacts with a wavelength-dependent
+    /// material, since the path direction is then only valid for the hero
+    /// wavelength.
+    pub fn terminate_secondary(&mut self) {
+        if self.secondary_terminated() {
+            return;
+        }
+
+        for pdf in &mut self.pdf[1..] {
+            *pdf = 0.0;
+        }
+        self.pdf[0] /= N_SPECTRUM_SAMPLES as f64;
+    }
+
+    /// Upsamples an RGB color at each of the wavelengths.
+    ///
+    /// # Arguments
+    ///
+    /// * `rgb` - The linear RGB color to upsample
+    pub fn upsample(&self, rgb: &Color) -> SampledSpectrum {
+        let mut values = [0.0; N_SPECTRUM_SAMPLES];
+        for (value, lambda) in values.iter_mut().zip(self.lambda) {
+            *value = rgb_to_spectrum(rgb, lambda);
+        }
+        SampledSpectrum::new(values)
+    }
+
+    /// Converts spectral radiance at these wavelengths to linear sRGB.
+    ///
+    /// The result is a Monte Carlo estimate that goes through CIE XYZ, so
+    /// averaging it over many samples gives the color of the spectrum.
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - The radiance at each wavelength
+    pub fn to_rgb(&self, s: &SampledSpectrum) -> Color {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let mut rgb = Color::default();
+        for i in 0..N_SPECTRUM_SAMPLES {
+            if self.pdf[i] != 0.0 {
+                rgb += (s[i] / (self.pdf[i] * range)) * wavelength_to_rgb(self.lambda[i]);
+            }
+        }
+        rgb / N_SPECTRUM_SAMPLES as f64
+    }
+}
+
+/// Spectral values at the wavelengths of a `SampledWavelengths`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SampledSpectrum {
+    /// The value at each wavelength
+    values: [f64; N_SPECTRUM_SAMPLES],
+}
+
+impl SampledSpectrum {
+    /// Creates a new sampled spectrum from its values.
+    pub fn new(values: [f64; N_SPECTRUM_SAMPLES]) -> Self {
+        Self { values }
+    }
+
+    /// Creates a sampled spectrum with the same value at every wavelength.
+    pub fn splat(value: f64) -> Self {
+        Self::new([value; N_SPECTRUM_SAMPLES])
+    }
+
+    /// Returns the largest value at any of the wavelengths
+    pub fn max_value(&self) -> f64 {
+        self.values.iter().fold(f64::NEG_INFINITY, |m, &v| m.max(v))
+    }
+}
+
+impl Default for SampledSpectrum {
+    fn default() -> Self {
+        Self::splat(0.0)
+    }
+}
+
+impl Index<usize> for SampledSpectrum {
+    type Output = f64;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.values[index]
+    }
+}
+
+impl Add for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn add(mut self, rhs: Self) -> Self::Output {
+        self += rhs;
+        self
+    }
+}
+
+impl AddAssign for SampledSpectrum {
+    fn add_assign(&mut self, rhs: Self) {
+        for (a, b) in self.values.iter_mut().zip(rhs.values) {
+            *a += b;
+        }
+    }
+}
+
+impl Mul for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn mul(mut self, rhs: Self) -> Self::Output {
+        for (a, b) in self.values.iter_mut().zip(rhs.values) {
+            *a *= b;
+        }
+        self
+    }
+}
+
+impl Mul<f64> for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn mul(mut self, rhs: f64) -> Self::Output {
+        for a in &mut self.values {
+            *a *= rhs;
+        }
+        self
+    }
+}
+
+/// Smooth Hermite interpolation between 0 at `edge0` and 1 at `edge1`.
+fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
@@ -4,12 +4,13 @@
 //! It implements the `Hittable` trait, providing ray-sphere intersection
 //! testing using the quadratic formula.
 
-use std::sync::Arc;
+use std::{f64, sync::Arc};
 
 use crate::{
-    hittable::{HitRecord, Hittable},
+    hittable::{HitRecord, Hittable, SurfaceSample},
     interval::Interval,
     material::{Material, Metal},
+    sampler::Sampler,
     vec3::{Color, Point3, Vec3},
 };
 
@@ -54,6 +55,30 @@ impl Sphere {
     pub fn radius(&self) -> f64 {
         self.radius
     }
+
+    /// Returns the surface area of the sphere
+    pub fn area(&self) -> f64 {
+        4.0 * f64::consts::PI * self.radius * self.radius
+    }
+
+    /// Computes the surface coordinates of a point on the unit sphere.
+    ///
+    /// `u` is the angle around the Y axis from X=-1, and `v` is the angle
+    /// from Y=-1 to Y=+1, both normalized to \[0,1\].
+    ///
+    /// # Arguments
+    ///
+    /// * `p` - A point on the sphere of radius one, centered at the origin
+    ///
+    /// # Returns
+    ///
+    /// The `(u, v)` surface coordinates of the point
+    fn get_sphere_uv(p: &Point3) -> (f64, f64) {
+        let theta = f64::acos(-p.y());
+        let phi = f64::atan2(-p.z(), p.x()) + f64::consts::PI;
+
+        (phi / (2.0 * f64::consts::PI), theta / f64::consts::PI)
+    }
 }
 
 impl Default for Sphere {
@@ -78,12 +103,18 @@ impl Hittable for Sphere {
     ///
     /// * `r` - The ray to test for intersection
     /// * `ray_t` - The interval along the ray to check for intersection
+    /// * `_sampler` - Unused; spheres are intersected deterministically
     ///
     /// # Returns
     ///
     /// If there is an intersection, returns a `HitRecord` containing the
     /// intersection details. Otherwise returns `None`.
-    fn hit(&self, r: &crate::ray::Ray, ray_t: Interval) -> Option<HitRecord> {
+    fn hit(
+        &self,
+        r: &crate::ray::Ray,
+        ray_t: Interval,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
         let oc = self.center() - r.origin();
         let a = r.direction().length_squared();
         let h = r.direction().dot(&oc);
@@ -109,13 +140,51 @@ impl Hittable for Sphere {
             t: root,
             p: r.at(root),
             normal: Vec3::default(),
+            u: 0.0,
+            v: 0.0,
             front_face: false,
+            in_medium: false,
             mat: Some(self.mat.clone()),
         };
 
         let outward_normal = (hit_record.p - self.center) / self.radius;
         hit_record.set_face_normal(r, &outward_normal);
+        (hit_record.u, hit_record.v) = Self::get_sphere_uv(&outward_normal);
 
         Some(hit_record)
     }
+
+    /// Samples a point uniformly over the area of the sphere.
+    fn sample_surface(&self, sampler: &mut dyn Sampler) -> Option<SurfaceSample> {
+        let outward_normal = Vec3::random_unit_vector(sampler);
+        let (u, v) = Self::get_sphere_uv(&outward_normal);
+
+        Some(SurfaceSample {
+            rec: HitRecord {
+                p: self.center + self.radius.abs() * outward_normal,
+                normal: outward_normal,
+                mat: Some(self.mat.clone()),
+                t: 0.0,
+                u,
+                v,
+                front_face: true,
+                in_medium: false,
+            },
+            pdf: 1.0 / self.area(),
+        })
+    }
+
+    /// Returns the uniform area density, 1 / (4 pi r^2), for points on the sphere.
+    fn surface_pdf(&self, p: &Point3) -> f64 {
+        let radius = self.radius.abs();
+        match ((*p - self.center).length() - radius).abs() <= 1.0e-6 * radius.max(1.0) {
+            true => 1.0 / self.area(),
+            false => 0.0,
+        }
+    }
+
+    /// Appends the material of the sphere.
+    fn collect_materials(&self, materials: &mut Vec<Arc<dyn Material>>) {
+        materials.push(self.mat.clone());
+    }
 }
@@ -0,0 +1,163 @@
+//! Subsurface scattering material for the raytracer.
+//!
+//! This module provides a `Subsurface` material for translucent objects such
+//! as skin, wax, soap and marble. Light refracts into the object through a
+//! `Dielectric` boundary and then performs a volumetric random walk inside,
+//! scattering and being absorbed until it refracts back out.
+//!
+//! The object must be closed, so that every ray that enters it hits its
+//! boundary again from the inside.
+
+use crate::{
+    hittable::HitRecord,
+    material::{Dielectric, HenyeyGreenstein, Material, Scatter},
+    ray::Ray,
+    sampler::Sampler,
+    vec3::Color,
+};
+
+/// A translucent material with volumetric scattering inside a dielectric boundary.
+///
+/// The random walk happens one segment at a time: whenever a ray that
+/// traveled through the interior reaches the boundary again, a free-flight
+/// distance is sampled. If it falls short of the boundary the ray scatters
+/// at that interior point; otherwise it interacts with the boundary like a
+/// `Dielectric`.
+pub struct Subsurface {
+    /// The boundary between the interior and the outside
+    boundary: Dielectric,
+    /// The scattering coefficient per unit distance for each color component
+    scattering: Color,
+    /// The absorption coefficient per unit distance for each color component
+    absorption: Color,
+    /// The phase function used at interior scattering events
+    phase: HenyeyGreenstein,
+}
+
+impl Subsurface {
+    /// Creates a new subsurface material with isotropic interior scattering.
+    ///
+    /// # Arguments
+    ///
+    /// * `refraction_index` - The refractive index of the boundary
+    /// * `scattering` - The scattering coefficient per unit distance
+    /// * `absorption` - The absorption coefficient per unit distance
+    pub fn new(refraction_index: f64, scattering: Color, absorption: Color) -> Self {
+        Self {
+            boundary: Dielectric::new(refraction_index),
+            scattering,
+            absorption,
+            phase: HenyeyGreenstein::new(Color::new(1.0, 1.0, 1.0), 0.0),
+        }
+    }
+
+    /// Returns this material with an anisotropic interior phase function.
+    ///
+    /// # Arguments
+    ///
+    /// * `g` - The Henyey-Greenstein asymmetry parameter; most organic
+    ///   materials scatter forward, with `g` around 0.8
+    pub fn with_anisotropy(mut self, g: f64) -> Self {
+        self.phase = HenyeyGreenstein::new(Color::new(1.0, 1.0, 1.0), g);
+        self
+    }
+
+    /// Returns the extinction coefficient (scattering plus absorption).
+    fn extinction(&self) -> Color {
+        self.scattering + self.absorption
+    }
+}
+
+impl Material for Subsurface {
+    /// Scatters the ray at the boundary or inside the volume.
+    ///
+    /// Rays arriving from the outside interact with the dielectric boundary.
+    /// Rays arriving from the inside sample a free-flight distance along
+    /// their path, using one color channel chosen at random and weighting by
+    /// the average density over all channels so the estimate stays unbiased
+    /// for every color. The material has no density to evaluate, so every
+    /// scattered direction is reported as specular.
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
+    ) -> Scatter {
+        if rec.front_face {
+            return self
+                .boundary
+                .scatter(r_in, rec, attenuation, scattered, sampler);
+        }
+
+        let extinction = self.extinction();
+        let ray_length = r_in.direction().length();
+        let distance_to_boundary = rec.t * ray_length;
+
+        let (u_channel, u_distance) = sampler.get_2d();
+        let channel = ((u_channel * 3.0) as usize).min(2);
+        let distance = match extinction[channel] > 0.0 {
+            true => -f64::ln(1.0 - u_distance) / extinction[channel],
+            false => f64::INFINITY,
+        };
+
+        let transmittance = |d: f64| {
+            Color::new(
+                f64::exp(-extinction.x() * d),
+                f64::exp(-extinction.y() * d),
+                f64::exp(-extinction.z() * d),
+            )
+        };
+
+        if distance < distance_to_boundary {
+            // Scatter inside the volume.
+            let tr = transmittance(distance);
+            let density = extinction * tr;
+            let pdf = (density.x() + density.y() + density.z()) / 3.0;
+            if pdf <= 0.0 {
+                return Scatter::Absorbed;
+            }
+
+            let interior = HitRecord {
+                p: r_in.at(distance / ray_length),
+                ..Default::default()
+            };
+            let mut phase_weight = Color::default();
+            if self
+                .phase
+                .scatter(r_in, &interior, &mut phase_weight, scattered, sampler)
+                == Scatter::Absorbed
+            {
+                return Scatter::Absorbed;
+            }
+
+            *attenuation = (self.scattering * tr) / pdf;
+            return Scatter::Specular;
+        }
+
+        // Reach the boundary and leave (or reflect back into) the volume.
+        let tr = transmittance(distance_to_boundary);
+        let pdf = (tr.x() + tr.y() + tr.z()) / 3.0;
+        if pdf <= 0.0 {
+            return Scatter::Absorbed;
+        }
+
+        let mut boundary_attenuation = Color::default();
+        if self
+            .boundary
+            .scatter(r_in, rec, &mut boundary_attenuation, scattered, sampler)
+            == Scatter::Absorbed
+        {
+            return Scatter::Absorbed;
+        }
+
+        *attenuation = (tr * boundary_attenuation) / pdf;
+        Scatter::Specular
+    }
+
+    /// Returns true if the boundary is dispersive.
+    fn is_dispersive(&self) -> bool {
+        self.boundary.is_dispersive()
+    }
+}
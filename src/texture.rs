@@ -0,0 +1,205 @@
+//! Texture implementations for the raytracer.
+//!
+//! This module provides the `Texture` trait and its implementations, which
+//! allow material parameters to vary across a surface:
+//! - `SolidColor`: A constant color everywhere
+//! - `CheckerTexture`: A 3D checker pattern alternating between two textures
+//! - `ChannelTexture`: A single channel of another texture, as a gray value
+//! - `EncodedTexture`: Another texture's encoded colors, decoded to the
+//!   linear working color space
+//!
+//! Textures return linear colors in the render's working color space.
+//! Colors encoded for display, such as those of 8-bit images or picked in
+//! other tools, should be decoded with `SolidColor::from_encoded` or
+//! `EncodedTexture`; data such as roughness or metalness should not.
+
+use std::sync::Arc;
+
+use crate::{
+    colorspace::ColorSpace,
+    vec3::{Color, Point3},
+};
+
+/// A trait for textures that can be sampled at a surface point.
+///
+/// Textures are looked up with the surface coordinates `(u, v)` of the hit
+/// point as well as the hit point itself, so both image-like and solid
+/// (procedural) textures can be expressed.
+pub trait Texture: Send + Sync {
+    /// Returns the texture color at the given surface coordinates and point.
+    ///
+    /// # Arguments
+    ///
+    /// * `u` - The horizontal surface coordinate in \[0,1\]
+    /// * `v` - The vertical surface coordinate in \[0,1\]
+    /// * `p` - The point on the surface
+    ///
+    /// # Returns
+    ///
+    /// The texture color at that location
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+}
+
+/// A texture with the same color everywhere.
+pub struct SolidColor {
+    /// The color returned for every lookup
+    albedo: Color,
+}
+
+impl SolidColor {
+    /// Creates a new solid color texture.
+    ///
+    /// # Arguments
+    ///
+    /// * `albedo` - The color of the texture
+    pub fn new(albedo: Color) -> Self {
+        Self { albedo }
+    }
+
+    /// Creates a new solid color texture from its red, green and blue components.
+    pub fn from_rgb(red: f64, green: f64, blue: f64) -> Self {
+        Self::new(Color::new(red, green, blue))
+    }
+
+    /// Creates a new solid gray texture, used for scalar material parameters.
+    pub fn gray(value: f64) -> Self {
+        Self::new(Color::new(value, value, value))
+    }
+
+    /// Creates a new solid color texture from a color encoded in a color space.
+    ///
+    /// # Arguments
+    ///
+    /// * `color` - The encoded color, such as an sRGB color from a color picker
+    /// * `space` - The color space the color is encoded in
+    /// * `working` - The working color space of the render
+    pub fn from_encoded(color: Color, space: ColorSpace, working: ColorSpace) -> Self {
+        Self::new(space.decode_color(color, working))
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.albedo
+    }
+}
+
+/// A solid 3D checker pattern alternating between two textures.
+pub struct CheckerTexture {
+    /// Inverse of the size of a single checker cell
+    inv_scale: f64,
+    /// Texture used for even cells
+    even: Arc<dyn Texture>,
+    /// Texture used for odd cells
+    odd: Arc<dyn Texture>,
+}
+
+impl CheckerTexture {
+    /// Creates a new checker texture from two textures.
+    ///
+    /// # Arguments
+    ///
+    /// * `scale` - The size of a single checker cell
+    /// * `even` - The texture used for even cells
+    /// * `odd` - The texture used for odd cells
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        Self {
+            inv_scale: 1.0 / scale,
+            even,
+            odd,
+        }
+    }
+
+    /// Creates a new checker texture alternating between two solid colors.
+    pub fn from_colors(scale: f64, c1: Color, c2: Color) -> Self {
+        Self::new(
+            scale,
+            Arc::new(SolidColor::new(c1)),
+            Arc::new(SolidColor::new(c2)),
+        )
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let x_integer = (self.inv_scale * p.x()).floor() as i64;
+        let y_integer = (self.inv_scale * p.y()).floor() as i64;
+        let z_integer = (self.inv_scale * p.z()).floor() as i64;
+
+        match (x_integer + y_integer + z_integer) % 2 == 0 {
+            true => self.even.value(u, v, p),
+            false => self.odd.value(u, v, p),
+        }
+    }
+}
+
+/// A texture that exposes one channel of another texture as a gray value.
+///
+/// This is useful for packed parameter textures, such as the glTF
+/// metallic-roughness texture which stores roughness in the green channel
+/// and metalness in the blue channel.
+pub struct ChannelTexture {
+    /// The packed texture to read from
+    texture: Arc<dyn Texture>,
+    /// The channel index to read (0 = red, 1 = green, 2 = blue)
+    channel: usize,
+}
+
+impl ChannelTexture {
+    /// Creates a new channel texture.
+    ///
+    /// # Arguments
+    ///
+    /// * `texture` - The packed texture to read from
+    /// * `channel` - The channel index to read (clamped to \[0,2\])
+    pub fn new(texture: Arc<dyn Texture>, channel: usize) -> Self {
+        Self {
+            texture,
+            channel: channel.min(2),
+        }
+    }
+}
+
+impl Texture for ChannelTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let value = self.texture.value(u, v, p)[self.channel];
+        Color::new(value, value, value)
+    }
+}
+
+/// A texture whose colors are encoded in a color space, decoded on lookup.
+///
+/// The colors of the wrapped texture are decoded with the transfer function
+/// of their color space and converted to the linear working color space.
+pub struct EncodedTexture {
+    /// The texture holding encoded colors
+    texture: Arc<dyn Texture>,
+    /// The color space the colors are encoded in
+    space: ColorSpace,
+    /// The working color space of the render
+    working: ColorSpace,
+}
+
+impl EncodedTexture {
+    /// Creates a new encoded texture.
+    ///
+    /// # Arguments
+    ///
+    /// * `texture` - The texture holding encoded colors
+    /// * `space` - The color space the colors are encoded in
+    /// * `working` - The working color space of the render
+    pub fn new(texture: Arc<dyn Texture>, space: ColorSpace, working: ColorSpace) -> Self {
+        Self {
+            texture,
+            space,
+            working,
+        }
+    }
+}
+
+impl Texture for EncodedTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.space
+            .decode_color(self.texture.value(u, v, p), self.working)
+    }
+}
@@ -0,0 +1,172 @@
+//! Tile scheduling for the raytracer.
+//!
+//! The camera splits the image into square tiles and renders them in
+//! parallel, so rayon can balance the work over the whole image instead of
+//! one scanline at a time, and neighboring rays, which tend to hit the same
+//! objects, are traced together. This module provides:
+//! - `Tile`: A rectangle of pixels rendered as one unit of work
+//! - `TileOrder`: The order tiles are handed out in
+//! - `tiles`: Splits an image into tiles in a given order
+
+/// The order in which tiles are handed out for rendering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TileOrder {
+    /// Row by row from the top of the image, each row from left to right
+    #[default]
+    Scanline,
+    /// Outward from the center of the image, where the subject usually is
+    Spiral,
+    /// Along a Hilbert curve, which keeps consecutive tiles next to each other
+    Hilbert,
+}
+
+/// A rectangle of pixels rendered as one unit of work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    /// The horizontal coordinate of the tile's first pixel
+    pub x: u32,
+    /// The vertical coordinate of the tile's first pixel
+    pub y: u32,
+    /// The width of the tile in pixels
+    pub width: u32,
+    /// The height of the tile in pixels
+    pub height: u32,
+}
+
+impl Tile {
+    /// Returns the coordinates of the tile's pixels, row by row.
+    pub fn pixels(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        (self.y..self.y + self.height)
+            .flat_map(move |j| (self.x..self.x + self.width).map(move |i| (i, j)))
+    }
+}
+
+/// Splits an image into tiles.
+///
+/// Tiles on the right and top edges are cut to fit inside the image.
+///
+/// # Arguments
+///
+/// * `width` - The width of the image in pixels
+/// * `height` - The height of the image in pixels
+/// * `tile_size` - The width and height of a tile in pixels
+/// * `order` - The order of the tiles
+///
+/// # Returns
+///
+/// Tiles covering every pixel of the image exactly once, in the given order
+pub fn tiles(width: u32, height: u32, tile_size: u32, order: TileOrder) -> Vec<Tile> {
+    let tile_size = tile_size.max(1);
+    let columns = width.div_ceil(tile_size);
+    let rows = height.div_ceil(tile_size);
+
+    let cells = match order {
+        TileOrder::Scanline => (0..rows)
+            .rev()
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .collect(),
+        TileOrder::Spiral => spiral(columns, rows),
+        TileOrder::Hilbert => hilbert(columns, rows),
+    };
+
+    cells
+        .into_iter()
+        .map(|(column, row)| {
+            let x = column * tile_size;
+            let y = row * tile_size;
+            Tile {
+                x,
+                y,
+                width: tile_size.min(width - x),
+                height: tile_size.min(height - y),
+            }
+        })
+        .collect()
+}
+
+/// Orders the cells of a grid along a square spiral from its center.
+///
+/// # Arguments
+///
+/// * `columns` - The number of columns of the grid
+/// * `rows` - The number of rows of the grid
+///
+/// # Returns
+///
+/// The column and row of every cell
+fn spiral(columns: u32, rows: u32) -> Vec<(u32, u32)> {
+    let count = columns as usize * rows as usize;
+    let mut cells = Vec::with_capacity(count);
+    let (mut x, mut y) = ((columns as i64 - 1) / 2, (rows as i64 - 1) / 2);
+    let directions = [(1, 0), (0, -1), (-1, 0), (0, 1)];
+
+    // Walk legs of length 1, 1, 2, 2, 3, 3, ... turning after each, and
+    // keep the cells that are inside the grid.
+    let mut leg = 0;
+    while cells.len() < count {
+        let (dx, dy) = directions[leg % 4];
+        for _ in 0..leg / 2 + 1 {
+            if (0..columns as i64).contains(&x) && (0..rows as i64).contains(&y) {
+                cells.push((x as u32, y as u32));
+            }
+            x += dx;
+            y += dy;
+        }
+        leg += 1;
+    }
+
+    cells
+}
+
+/// Orders the cells of a grid along a Hilbert curve.
+///
+/// The curve fills the smallest power-of-two square containing the grid,
+/// and the cells outside the grid are skipped.
+///
+/// # Arguments
+///
+/// * `columns` - The number of columns of the grid
+/// * `rows` - The number of rows of the grid
+///
+/// # Returns
+///
+/// The column and row of every cell
+fn hilbert(columns: u32, rows: u32) -> Vec<(u32, u32)> {
+    let n = columns.max(rows).next_power_of_two() as u64;
+    (0..n * n)
+        .map(|d| hilbert_point(n, d))
+        .filter(|&(x, y)| x < columns && y < rows)
+        .collect()
+}
+
+/// Returns the cell at a distance along the Hilbert curve filling an n by n square.
+///
+/// # Arguments
+///
+/// * `n` - The side of the square, a power of two
+/// * `d` - The distance along the curve
+///
+/// # Returns
+///
+/// The column and row of the cell
+fn hilbert_point(n: u64, mut d: u64) -> (u32, u32) {
+    let (mut x, mut y) = (0, 0);
+    let mut s = 1;
+    while s < n {
+        let rx = 1 & (d / 2);
+        let ry = 1 & (d ^ rx);
+        // Rotate the quadrant so the sub-curves join up.
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += s * rx;
+        y += s * ry;
+        d /= 4;
+        s *= 2;
+    }
+    (x as u32, y as u32)
+}
@@ -0,0 +1,212 @@
+//! Exposure and tone mapping for the raytracer.
+//!
+//! Rendered images hold linear scene radiance, which is unbounded: lights
+//! and their reflections are often many times brighter than white. This
+//! module maps the linear framebuffer to display values in \[0,1\] before
+//! it is encoded for output:
+//! - `Exposure`: Scales the image, either in stops or from the settings of
+//!   a physical camera (ISO, shutter time and aperture)
+//! - `ToneMap`: Compresses the exposed image into the display range with
+//!   Reinhard, extended Reinhard, Hable (filmic), fitted ACES or AgX curves
+
+use crate::{
+    colorspace::{ColorMatrix, transform},
+    vec3::Color,
+};
+
+/// How much light reaches the image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Exposure {
+    /// Exposure compensation in stops (EV): every stop doubles the brightness
+    Ev(f64),
+    /// The exposure of a physical camera, with scene radiance taken in
+    /// cd/m², saturating at the brightness the settings would just clip
+    Physical {
+        /// The sensitivity of the sensor, such as 100.0
+        iso: f64,
+        /// The shutter time in seconds, such as 1.0 / 125.0
+        shutter: f64,
+        /// The f-number of the aperture, such as 16.0
+        aperture: f64,
+    },
+}
+
+impl Default for Exposure {
+    /// Returns no exposure compensation, which leaves the image unchanged.
+    fn default() -> Self {
+        Exposure::Ev(0.0)
+    }
+}
+
+impl Exposure {
+    /// Returns the factor the linear image is multiplied by.
+    ///
+    /// The physical exposure follows the saturation-based sensitivity
+    /// model: the settings give an exposure value at ISO 100 of
+    /// `EV100 = log2(N² / t × 100 / S)`, and the radiance that just
+    /// saturates the sensor is `1.2 × 2^EV100`, which is mapped to 1.0.
+    pub fn scale(&self) -> f64 {
+        match *self {
+            Exposure::Ev(ev) => ev.exp2(),
+            Exposure::Physical {
+                iso,
+                shutter,
+                aperture,
+            } => {
+                let ev100 = (aperture * aperture / shutter * 100.0 / iso).log2();
+                1.0 / (1.2 * ev100.exp2())
+            }
+        }
+    }
+}
+
+/// The curve that compresses exposed colors into the display range.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ToneMap {
+    /// No tone mapping: components above 1.0 clip to white
+    #[default]
+    Clamp,
+    /// Reinhard's L / (1 + L) applied to luminance, which keeps hues but
+    /// never reaches white
+    Reinhard,
+    /// Reinhard's curve extended to reach white at a given luminance
+    ExtendedReinhard {
+        /// The smallest luminance mapped to white
+        white: f64,
+    },
+    /// John Hable's filmic curve from Uncharted 2, with a toe and shoulder
+    Hable,
+    /// Krzysztof Narkowicz's fit of the ACES reference rendering and output
+    /// transforms, with Stephen Hill's gamut matrices
+    Aces,
+    /// Troy Sobotka's AgX, which desaturates highlights smoothly towards white
+    Agx,
+}
+
+impl ToneMap {
+    /// Maps an exposed linear color to a linear display color.
+    ///
+    /// # Arguments
+    ///
+    /// * `color` - The linear sRGB color after exposure
+    ///
+    /// # Returns
+    ///
+    /// The linear display color, in \[0,1\] for every curve but `Clamp`
+    pub fn apply(&self, color: Color) -> Color {
+        match *self {
+            ToneMap::Clamp => color,
+            ToneMap::Reinhard => scale_luminance(color, |l| l / (1.0 + l)),
+            ToneMap::ExtendedReinhard { white } => {
+                scale_luminance(color, |l| l * (1.0 + l / (white * white)) / (1.0 + l))
+            }
+            ToneMap::Hable => hable(color),
+            ToneMap::Aces => aces(color),
+            ToneMap::Agx => agx(color),
+        }
+    }
+}
+
+/// Maps the luminance of a color with a curve, keeping its chromaticity.
+fn scale_luminance(color: Color, curve: impl Fn(f64) -> f64) -> Color {
+    let luminance = color.luminance();
+    match luminance > 0.0 {
+        true => color * (curve(luminance) / luminance),
+        false => Color::default(),
+    }
+}
+
+/// Applies Hable's filmic curve to every component.
+///
+/// The curve is applied with its usual exposure bias of 2.0 and scaled so
+/// that a linear value of 11.2 maps to white.
+fn hable(color: Color) -> Color {
+    const EXPOSURE_BIAS: f64 = 2.0;
+    const WHITE: f64 = 11.2;
+
+    let curve = |x: f64| {
+        const A: f64 = 0.15; // shoulder strength
+        const B: f64 = 0.50; // linear strength
+        const C: f64 = 0.10; // linear angle
+        const D: f64 = 0.20; // toe strength
+        const E: f64 = 0.02; // toe numerator
+        const F: f64 = 0.30; // toe denominator
+        (x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F) - E / F
+    };
+    let white_scale = 1.0 / curve(WHITE);
+    Color::new(
+        curve(EXPOSURE_BIAS * color.x().max(0.0)) * white_scale,
+        curve(EXPOSURE_BIAS * color.y().max(0.0)) * white_scale,
+        curve(EXPOSURE_BIAS * color.z().max(0.0)) * white_scale,
+    )
+}
+
+/// Applies the fitted ACES transforms.
+///
+/// The color is converted from sRGB primaries to the ACES rendering space,
+/// passed through the rational fit of the reference rendering and output
+/// transforms, and converted back.
+fn aces(color: Color) -> Color {
+    const INPUT: ColorMatrix = [
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ];
+    const OUTPUT: ColorMatrix = [
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ];
+
+    let fit = |v: f64| {
+        let v = v.max(0.0);
+        (v * (v + 0.0245786) - 0.000090537) / (v * (0.983729 * v + 0.4329510) + 0.238081)
+    };
+    let v = transform(&INPUT, color);
+    let v = transform(&OUTPUT, Color::new(fit(v.x()), fit(v.y()), fit(v.z())));
+    Color::new(
+        v.x().clamp(0.0, 1.0),
+        v.y().clamp(0.0, 1.0),
+        v.z().clamp(0.0, 1.0),
+    )
+}
+
+/// Applies AgX with its default look.
+///
+/// The color is moved into AgX's inset working space, encoded
+/// logarithmically over 16.5 stops around middle grey, passed through the
+/// polynomial fit of AgX's sigmoid and moved back out of the inset space.
+/// The sigmoid produces values encoded for a 2.2 gamma display, which are
+/// decoded to linear.
+fn agx(color: Color) -> Color {
+    const INSET: ColorMatrix = [
+        [0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+        [0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+        [0.0423756549057051, 0.0784336, 0.879142973793104],
+    ];
+    const OUTSET: ColorMatrix = [
+        [1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+        [-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+        [-0.0529716355144438, -0.0980434501171241, 1.15107367264116],
+    ];
+    const MIN_EV: f64 = -12.47393;
+    const MAX_EV: f64 = 4.026069;
+
+    let sigmoid = |v: f64| {
+        let x = (v.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    };
+    let v = transform(&INSET, color);
+    let v = transform(
+        &OUTSET,
+        Color::new(sigmoid(v.x()), sigmoid(v.y()), sigmoid(v.z())),
+    );
+    Color::new(
+        v.x().clamp(0.0, 1.0).powf(2.2),
+        v.y().clamp(0.0, 1.0).powf(2.2),
+        v.z().clamp(0.0, 1.0).powf(2.2),
+    )
+}
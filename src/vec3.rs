@@ -5,10 +5,10 @@
 //! vector operations and provides utility functions for random vector generation
 //! and geometric calculations.
 
+use std::f64::consts::PI;
 use std::ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub};
 
-use crate::random_double;
-use crate::random_double_range;
+use crate::sampler::Sampler;
 
 /// Type alias for using Vec3 as a point in 3D space
 pub type Point3 = Vec3;
@@ -91,47 +91,49 @@ impl Vec3 {
     }
 
     /// Generates a random vector with components in [0,1)
-    pub fn random_vec() -> Vec3 {
-        Vec3::new(random_double(), random_double(), random_double())
+    pub fn random_vec(sampler: &mut dyn Sampler) -> Vec3 {
+        Vec3::new(sampler.get_1d(), sampler.get_1d(), sampler.get_1d())
     }
 
     /// Generates a random vector with components in [min,max)
-    pub fn random_vec_range(min: f64, max: f64) -> Vec3 {
-        Vec3::new(
-            random_double_range(min, max),
-            random_double_range(min, max),
-            random_double_range(min, max),
-        )
+    pub fn random_vec_range(min: f64, max: f64, sampler: &mut dyn Sampler) -> Vec3 {
+        min * Vec3::new(1.0, 1.0, 1.0) + (max - min) * Vec3::random_vec(sampler)
     }
 
     /// Generates a random unit vector (uniformly distributed on unit sphere)
-    pub fn random_unit_vector() -> Vec3 {
-        loop {
-            let p = Vec3::random_vec_range(-1.0, 1.0);
-            let lensq = p.length_squared();
-            if 1.0e-160 < lensq && lensq <= 1.0 {
-                return p / lensq.sqrt();
-            }
-        }
+    ///
+    /// The direction is mapped directly from a 2D sample, so well-spread
+    /// samples give well-spread directions.
+    pub fn random_unit_vector(sampler: &mut dyn Sampler) -> Vec3 {
+        let (u1, u2) = sampler.get_2d();
+        let z = 1.0 - 2.0 * u1;
+        let r = f64::sqrt(f64::max(0.0, 1.0 - z * z));
+        let phi = 2.0 * PI * u2;
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
     }
 
     /// Generates a random vector in the unit disk (x,y plane)
-    pub fn random_in_unit_disk() -> Vec3 {
-        loop {
-            let p = Vec3::new(
-                random_double_range(-1.0, 1.0),
-                random_double_range(-1.0, 1.0),
-                0.0,
-            );
-            if p.length_squared() < 1.0 {
-                return p;
-            }
+    ///
+    /// The point is mapped from a 2D sample with Shirley's concentric
+    /// mapping, which keeps nearby samples nearby.
+    pub fn random_in_unit_disk(sampler: &mut dyn Sampler) -> Vec3 {
+        let (u1, u2) = sampler.get_2d();
+        let a = 2.0 * u1 - 1.0;
+        let b = 2.0 * u2 - 1.0;
+        if a == 0.0 && b == 0.0 {
+            return Vec3::default();
         }
+
+        let (r, theta) = match a.abs() > b.abs() {
+            true => (a, PI / 4.0 * (b / a)),
+            false => (b, PI / 2.0 - PI / 4.0 * (a / b)),
+        };
+        Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
     }
 
     /// Generates a random vector on the hemisphere defined by the normal
-    pub fn random_on_hemisphere(normal: &Vec3) -> Vec3 {
-        let on_unit_sphere = Vec3::random_unit_vector();
+    pub fn random_on_hemisphere(normal: &Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        let on_unit_sphere = Vec3::random_unit_vector(sampler);
         match on_unit_sphere.dot(normal) > 0.0 {
             true => on_unit_sphere,
             false => -on_unit_sphere,
@@ -161,6 +163,11 @@ impl Vec3 {
     pub fn length(&self) -> f64 {
         f64::sqrt(self.length_squared())
     }
+
+    /// Returns the relative luminance of a linear sRGB color
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.x() + 0.7152 * self.y() + 0.0722 * self.z()
+    }
 }
 
 impl Default for Vec3 {
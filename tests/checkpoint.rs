@@ -0,0 +1,69 @@
+//! Tests that interrupted renders resume to the same film as uninterrupted ones.
+
+mod common;
+
+use std::{fs, path::PathBuf, time::Duration};
+
+use raytracing::checkpoint::Checkpoint;
+
+/// Returns a checkpoint path in the temporary directory, unique to the test.
+fn checkpoint_path(name: &str) -> PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("raytracing-{}-{}.ckpt", name, std::process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn resumed_render_matches_uninterrupted_render() {
+    let (world, _) = common::scene();
+    let mut cam = common::camera();
+    cam.samples_per_pass = 2;
+    let uninterrupted = cam.render_progressive(&world, |_| {});
+
+    let path = checkpoint_path("resume");
+    let mut cam = common::camera();
+    cam.samples_per_pass = 2;
+    cam.checkpoint_path = Some(path.clone());
+    // A zero time budget stops every render after its first pass.
+    cam.time_budget = Some(Duration::ZERO);
+    for pass in 1..=2 {
+        cam.render_progressive(&world, |_| {});
+        let checkpoint = Checkpoint::load(&path).expect("Error loading checkpoint");
+        assert_eq!(checkpoint.next_sample, 2 * pass);
+    }
+
+    cam.time_budget = None;
+    let resumed = cam.render_progressive(&world, |_| {});
+    fs::remove_file(&path).expect("Error removing checkpoint");
+
+    assert_eq!(
+        common::film_bytes(&resumed),
+        common::film_bytes(&uninterrupted)
+    );
+}
+
+#[test]
+fn mismatched_checkpoint_is_moved_aside() {
+    let (world, _) = common::scene();
+    let path = checkpoint_path("mismatch");
+    let mut cam = common::camera();
+    cam.samples_per_pass = 2;
+    cam.checkpoint_path = Some(path.clone());
+    cam.time_budget = Some(Duration::ZERO);
+    cam.render_progressive(&world, |_| {});
+    let saved = fs::read(&path).expect("Error reading checkpoint");
+
+    cam.max_depth += 1;
+    cam.time_budget = None;
+    let film = cam.render_progressive(&world, |_| {});
+    assert_eq!(film.total_samples(), 8 * film.pixels().len() as u64);
+
+    let mut rejected_path = path.clone().into_os_string();
+    rejected_path.push(".rejected");
+    let rejected = fs::read(&rejected_path).expect("Error reading rejected checkpoint");
+    assert_eq!(rejected, saved);
+
+    fs::remove_file(&path).expect("Error removing checkpoint");
+    fs::remove_file(&rejected_path).expect("Error removing rejected checkpoint");
+}
@@ -0,0 +1,77 @@
+//! Scenes and helpers shared by the integration tests.
+
+use std::sync::Arc;
+
+use raytracing::{
+    camera::Camera,
+    film::Film,
+    hittable_list::HittableList,
+    material::{Dielectric, DiffuseLight, Lambertian, Metal},
+    sphere::Sphere,
+    vec3::{Color, Point3, Vec3},
+};
+
+/// Returns the area light of the test scene.
+fn light() -> Sphere {
+    Sphere::new(
+        Point3::new(0.0, 3.0, 0.0),
+        0.75,
+        Arc::new(DiffuseLight::new(Color::new(8.0, 8.0, 8.0))),
+    )
+}
+
+/// Builds a small scene with diffuse, metal and glass spheres under an area light.
+///
+/// # Returns
+///
+/// The scene, and a list holding its light for integrators that sample lights
+pub fn scene() -> (HittableList, HittableList) {
+    let mut world = HittableList::new();
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, -100.5, 0.0),
+        100.0,
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::new(-1.0, 0.0, 0.0),
+        0.5,
+        Arc::new(Lambertian::new(Color::new(0.7, 0.3, 0.2))),
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, 0.0, 0.0),
+        0.5,
+        Arc::new(Dielectric::new(1.5)),
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::new(1.0, 0.0, 0.0),
+        0.5,
+        Arc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.2)),
+    )));
+    world.add(Box::new(light()));
+
+    let mut lights = HittableList::new();
+    lights.add(Box::new(light()));
+    (world, lights)
+}
+
+/// Returns a camera that renders the test scene quickly at a fixed seed.
+pub fn camera() -> Camera {
+    let mut cam = Camera::default();
+    cam.aspect_ratio = 1.5;
+    cam.image_width = 24;
+    cam.samples_per_pixel = 8;
+    cam.max_depth = 8;
+    cam.vfov = 40.0;
+    cam.lookfrom = Point3::new(0.0, 1.0, 4.0);
+    cam.lookat = Point3::new(0.0, 0.0, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+    cam.seed = 7;
+    cam
+}
+
+/// Returns the exact bytes of a film, so that films can be compared bit for bit.
+pub fn film_bytes(film: &Film) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    film.write_to(&mut bytes).expect("Error writing film");
+    bytes
+}
@@ -0,0 +1,80 @@
+//! Tests that renders do not depend on the number of threads they run on.
+
+mod common;
+
+use raytracing::{
+    bdpt::Bdpt,
+    integrator::{Integrator, PathTracer},
+    mlt::Mlt,
+};
+
+/// The number of threads of the multithreaded pools.
+const THREADS: usize = 4;
+
+/// Runs a closure on a rayon pool with the given number of threads.
+fn on_threads<T: Send>(threads: usize, f: impl FnOnce() -> T + Send) -> T {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .expect("Error building thread pool")
+        .install(f)
+}
+
+/// Renders the test scene with an integrator and returns the exact bytes of the film.
+fn render(integrator: fn() -> Box<dyn Integrator>, samples_per_pass: u32) -> Vec<u8> {
+    let (world, _) = common::scene();
+    let mut cam = common::camera();
+    cam.integrator = integrator();
+    cam.samples_per_pass = samples_per_pass;
+    common::film_bytes(&cam.render_progressive(&world, |_| {}))
+}
+
+/// Asserts that an integrator renders the same film on one and many threads.
+fn assert_thread_independent(integrator: fn() -> Box<dyn Integrator>) {
+    for samples_per_pass in [0, 2] {
+        let serial = on_threads(1, || render(integrator, samples_per_pass));
+        let parallel = on_threads(THREADS, || render(integrator, samples_per_pass));
+        assert!(
+            serial == parallel,
+            "films differ with {} samples per pass",
+            samples_per_pass
+        );
+    }
+}
+
+#[test]
+fn path_tracer_is_thread_independent() {
+    assert_thread_independent(|| Box::new(PathTracer));
+}
+
+#[test]
+fn bdpt_is_thread_independent() {
+    assert_thread_independent(|| Box::new(Bdpt::new(common::scene().1)));
+}
+
+#[test]
+fn mlt_is_thread_independent() {
+    // Metropolis light transport renders the whole image at once, without passes.
+    let render = || {
+        let (world, _) = common::scene();
+        let mut cam = common::camera();
+        let mut mlt = Mlt::new(Box::new(PathTracer));
+        mlt.bootstrap_samples = 1_000;
+        mlt.chains = 32;
+        cam.integrator = Box::new(mlt);
+        cam.prepare(&world);
+        let image = cam
+            .integrator
+            .render_image(&cam, &world)
+            .expect("Metropolis light transport renders the image itself");
+        image
+            .iter()
+            .flat_map(|c| [c.x(), c.y(), c.z()])
+            .map(f64::to_bits)
+            .collect::<Vec<_>>()
+    };
+
+    let serial = on_threads(1, render);
+    let parallel = on_threads(THREADS, render);
+    assert!(serial == parallel, "images differ");
+}
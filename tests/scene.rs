@@ -0,0 +1,61 @@
+//! Tests that scene descriptions survive being sent to another process.
+
+use raytracing::{
+    hittable::Hittable,
+    material::RefractiveIndex,
+    scene::{MaterialDescription, SceneDescription},
+    vec3::{Color, Point3},
+};
+
+#[test]
+fn every_description_round_trips() {
+    let materials = [
+        MaterialDescription::Lambertian(Color::new(0.1, 0.2, 0.3)),
+        MaterialDescription::Metal(Color::new(0.8, 0.6, 0.2), 0.25),
+        MaterialDescription::Dielectric(1.5),
+        MaterialDescription::DiffuseLight(Color::new(4.0, 4.0, 4.0)),
+        MaterialDescription::OrenNayar(Color::new(0.7, 0.5, 0.3), 20.0),
+        MaterialDescription::DispersiveDielectric(RefractiveIndex::Constant(1.33)),
+        MaterialDescription::DispersiveDielectric(RefractiveIndex::Cauchy { a: 1.5, b: 0.004 }),
+        MaterialDescription::DispersiveDielectric(RefractiveIndex::BK7),
+        MaterialDescription::Subsurface {
+            refraction_index: 1.4,
+            scattering: Color::new(2.0, 1.0, 0.5),
+            absorption: Color::new(0.01, 0.05, 0.1),
+            anisotropy: 0.8,
+        },
+        MaterialDescription::Principled {
+            base_color: Color::new(0.9, 0.1, 0.1),
+            metallic: 0.2,
+            roughness: 0.4,
+            specular: 0.5,
+            clearcoat: 0.3,
+            clearcoat_roughness: 0.05,
+            sheen: 0.1,
+            sheen_tint: 0.5,
+            transmission: 0.6,
+            ior: 1.45,
+        },
+    ];
+
+    let mut scene = SceneDescription::new();
+    for (i, material) in materials.into_iter().enumerate() {
+        scene.add_sphere(Point3::new(i as f64, 0.5, -1.0), 0.5, material);
+    }
+    scene.add_medium(
+        Point3::new(0.0, 1.0, 2.0),
+        1.5,
+        0.3,
+        Color::new(0.9, 0.9, 0.9),
+    );
+
+    let mut bytes = Vec::new();
+    scene.write_to(&mut bytes).unwrap();
+    let read = SceneDescription::read_from(&mut &bytes[..]).unwrap();
+    assert_eq!(read, scene);
+
+    // Every sphere brings its material and the medium its phase function.
+    let mut built = Vec::new();
+    read.build().collect_materials(&mut built);
+    assert_eq!(built.len(), materials.len() + 1);
+}